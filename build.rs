@@ -8,6 +8,18 @@ use std::{
     process::{Command, Stdio},
 };
 
+/// The C API of libmediagraph, a Bazel package that is copied into the mediapipe workspace.
+const MEDIAGRAPH_PACKAGE: &str = "mediagraph";
+/// The header of the C API.
+const MEDIAGRAPH_HEADER: &str = "mediagraph/mediagraph.h";
+
+/// A mediagraph library built outside of this crate.
+struct Prebuilt {
+    /// Directory of `libmediagraph.so`, `None` if it is on the default linker path.
+    lib_dir: Option<PathBuf>,
+    header: PathBuf,
+}
+
 struct CrossCompileInfo {
     pub sysroot: PathBuf,
     pub target_triple: String,
@@ -17,33 +29,34 @@ struct CrossCompileInfo {
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=MEDIAPIPE_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=MEDIAPIPE_INCLUDE_DIR");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let host_triple = env::var("HOST").unwrap();
-    let target_triple = env::var("TARGET").unwrap();
 
-    let cross_compile_info = if host_triple != target_triple {
-        // We are cross-compiling
-        let sysroot = PathBuf::from(env::var("CROSS_SYSROOT").expect("Cross-compiling requires CROSS_SYSROOT to be set"));
-        let deb_arch = env::var("CROSS_DEB_ARCH").expect("Cross-compiling requires CROSS_DEB_ARCH to be set");
-        let toolchain_prefix = env::var("CROSS_TOOLCHAIN_PREFIX").expect("Cross-compiling requires CROSS_TOOLCHAIN_PREFIX to be set");
-        Some(CrossCompileInfo {
-            sysroot,
-            target_triple,
-            deb_arch,
-            toolchain_prefix,
-        })
-    } else {
-        None
+    let header = match find_prebuilt() {
+        Some(prebuilt) => {
+            if let Some(lib_dir) = &prebuilt.lib_dir {
+                let library = lib_dir.join("libmediagraph.so");
+                println!("cargo:rerun-if-changed={}", library.display());
+                println!("cargo:rustc-link-search=native={}", lib_dir.display());
+                copy_to_target_dir(&library);
+            }
+            println!("cargo:rustc-link-lib=mediagraph");
+            prebuilt.header
+        }
+        None => {
+            bazel_build(cross_compile_info().as_ref());
+            PathBuf::from(MEDIAGRAPH_HEADER)
+        }
     };
 
-    bazel_build(cross_compile_info.as_ref());
-
     let b_builder = bindgen::Builder::default()
         .clang_arg("-xc++")
         .clang_arg("-std=c++14")
         .generate_comments(true)
-        .header("mediapipe/mediapipe/mediagraph.h")
+        .header(header.to_string_lossy())
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
@@ -57,10 +70,61 @@ fn main() {
     bindings.write_to_file(out_dir.join("bindings.rs")).expect("Couldn't write bindings!");
 }
 
+/// Finds an installed mediagraph library, from `MEDIAPIPE_LIB_DIR` or else pkg-config, so
+/// mediapipe does not have to be built with Bazel.
+///
+/// The header is looked up in `MEDIAPIPE_INCLUDE_DIR`, then in the pkg-config include paths. A
+/// library without its header fails the build, the header in this crate may not match it.
+fn find_prebuilt() -> Option<Prebuilt> {
+    let include_dir = env::var_os("MEDIAPIPE_INCLUDE_DIR").map(PathBuf::from);
+    let (lib_dir, include_dirs) = match env::var_os("MEDIAPIPE_LIB_DIR") {
+        Some(lib_dir) => (Some(PathBuf::from(lib_dir)), vec![]),
+        None => {
+            let library = pkg_config::Config::new().cargo_metadata(false).probe("mediagraph").ok()?;
+            (library.link_paths.first().cloned(), library.include_paths)
+        }
+    };
+
+    let header = match include_dir {
+        Some(include_dir) => find_header(std::slice::from_ref(&include_dir)).unwrap_or_else(|| panic!("mediagraph.h not found in MEDIAPIPE_INCLUDE_DIR {}", include_dir.display())),
+        None => find_header(&include_dirs).unwrap_or_else(|| panic!("Found a prebuilt mediagraph library but not its mediagraph.h, set MEDIAPIPE_INCLUDE_DIR to the directory of the header")),
+    };
+    println!("cargo:rerun-if-changed={}", header.display());
+
+    Some(Prebuilt { lib_dir, header })
+}
+
+/// Finds `mediagraph.h` directly in one of `include_dirs` or in their `mediapipe` subdirectory.
+fn find_header(include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    include_dirs.iter().flat_map(|dir| [dir.join("mediagraph.h"), dir.join("mediapipe").join("mediagraph.h")]).find(|header| header.is_file())
+}
+
+fn cross_compile_info() -> Option<CrossCompileInfo> {
+    let host_triple = env::var("HOST").unwrap();
+    let target_triple = env::var("TARGET").unwrap();
+
+    if host_triple != target_triple {
+        // We are cross-compiling
+        let sysroot = PathBuf::from(env::var("CROSS_SYSROOT").expect("Cross-compiling requires CROSS_SYSROOT to be set"));
+        let deb_arch = env::var("CROSS_DEB_ARCH").expect("Cross-compiling requires CROSS_DEB_ARCH to be set");
+        let toolchain_prefix = env::var("CROSS_TOOLCHAIN_PREFIX").expect("Cross-compiling requires CROSS_TOOLCHAIN_PREFIX to be set");
+        Some(CrossCompileInfo {
+            sysroot,
+            target_triple,
+            deb_arch,
+            toolchain_prefix,
+        })
+    } else {
+        None
+    }
+}
+
 fn bazel_build(cross_compile_info: Option<&CrossCompileInfo>) {
     println!("cargo:rerun-if-changed=mediapipe/mediapipe");
+    println!("cargo:rerun-if-changed={}", MEDIAGRAPH_PACKAGE);
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let mediapipe_src_dir = PathBuf::from("mediapipe");
+    copy_mediagraph_package(&mediapipe_src_dir);
 
     let mut bazel_command = Command::new("bazelisk");
     cmd_add_bazel_args(&mut bazel_command, &out_dir, &mediapipe_src_dir);
@@ -75,7 +139,7 @@ fn bazel_build(cross_compile_info: Option<&CrossCompileInfo>) {
         set_cross_toolchain(&mut bazel_command, &mediapipe_src_dir, cc_info);
     }
 
-    bazel_command.arg("mediapipe:mediagraph");
+    bazel_command.arg(format!("//{}:libmediagraph.so", MEDIAGRAPH_PACKAGE));
     let mut handle = bazel_command.stdout(Stdio::inherit()).spawn().expect("Failed to start mediapipe build");
     let exitcode = handle.wait().expect("Failed to build mediapipe");
     if !exitcode.success() {
@@ -97,7 +161,7 @@ fn bazel_build(cross_compile_info: Option<&CrossCompileInfo>) {
     }
     let bazel_bin_str = String::from_utf8(output.stdout).expect(bazel_build_fail_msg).replace("\n", "").replace("\r", "");
     let bazel_bin_path = PathBuf::from(bazel_bin_str);
-    let libmediagraph_folder_path = bazel_bin_path.join("mediapipe");
+    let libmediagraph_folder_path = bazel_bin_path.join(MEDIAGRAPH_PACKAGE);
     // TODO: handling for other os-ses
     let libmediagraph_file_path = libmediagraph_folder_path.join("libmediagraph.so");
     // Tell cargo to look for shared libraries in the specified directory
@@ -105,16 +169,32 @@ fn bazel_build(cross_compile_info: Option<&CrossCompileInfo>) {
     // Tell cargo to tell rustc to link the mediagraph shared library.
     println!("cargo:rustc-link-lib=mediagraph");
 
-    // Copy libmediagraph.so to main target directory
+    copy_to_target_dir(&libmediagraph_file_path);
+}
+
+/// Copies the C API package into the mediapipe workspace, where Bazel builds it against mediapipe.
+fn copy_mediagraph_package(mediapipe_src_dir: &Path) {
+    let package_dir = mediapipe_src_dir.join(MEDIAGRAPH_PACKAGE);
+    create_dir_all(&package_dir).unwrap_or_else(|e| panic!("Unable to create directory {}: {}", package_dir.display(), e));
+    for file in &["BUILD", "mediagraph.h", "mediagraph.cc"] {
+        let source = Path::new(MEDIAGRAPH_PACKAGE).join(file);
+        if let Err(e) = fs::copy(&source, package_dir.join(file)) {
+            panic!("Couldn't copy {} into {}: {}", source.display(), package_dir.display(), e)
+        }
+    }
+}
+
+/// Copies libmediagraph.so next to the executables, where it is loaded from at runtime.
+fn copy_to_target_dir(libmediagraph_file_path: &Path) {
     match get_cargo_target_dir() {
         Ok(target_dir) => {
             println!("Found main cargo target dir: {}\nAdding libmediagraph.so...", target_dir.display());
             let target_file_path = target_dir.join("libmediagraph.so");
-            if let Err(e) = fs::copy(&libmediagraph_file_path, &target_file_path) {
+            if let Err(e) = fs::copy(libmediagraph_file_path, &target_file_path) {
                 eprintln!("Failed to copy to {}: {}", target_file_path.display(), e);
             }
             let target_file_path_examples = target_dir.join("examples").join("libmediagraph.so");
-            if let Err(e) = fs::copy(&libmediagraph_file_path, &target_file_path_examples) {
+            if let Err(e) = fs::copy(libmediagraph_file_path, &target_file_path_examples) {
                 eprintln!("Failed to copy to {}: {}", target_file_path_examples.display(), e);
             }
         }
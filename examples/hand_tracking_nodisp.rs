@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use mediapipe::*;
 use opencv::prelude::*;
-use opencv::{imgproc, videoio, Result};
+use opencv::{videoio, Result};
 
 pub fn hand_tracking() -> Result<()> {
     let mut cap = videoio::VideoCapture::new(0, videoio::CAP_ANY)?;
@@ -15,24 +15,23 @@ pub fn hand_tracking() -> Result<()> {
     cap.set(videoio::CAP_PROP_FPS, 30.0)?;
 
     let mut detector = hands::HandDetector::default();
+    detector.set_pixel_format(PixelFormat::Bgr);
 
     let mut raw_frame = Mat::default();
-    let mut rgb_frame = Mat::default();
     let mut flip_frame = Mat::default();
     loop {
         cap.read(&mut raw_frame)?;
 
         let size = raw_frame.size()?;
         if size.width > 0 && !raw_frame.empty() {
-            imgproc::cvt_color(&raw_frame, &mut rgb_frame, imgproc::COLOR_BGR2RGB, 0)?;
-            opencv::core::flip(&rgb_frame, &mut flip_frame, 1)?; // horizontal
+            opencv::core::flip(&raw_frame, &mut flip_frame, 1)?; // horizontal
 
             println!("processing");
-            let result = detector.process(&mut flip_frame);
+            let result = detector.process(&flip_frame);
 
             if !result.is_empty() {
-                let landmark = result[0].data[0];
-                println!("LANDMARK: {} {} {}", landmark.x, landmark.y, landmark.z);
+                let landmark = result[0].landmarks.data[0];
+                println!("{:?} LANDMARK: {} {} {}", result[0].handedness, landmark.x, landmark.y, landmark.z);
             }
         } else {
             println!("WARN: Skip empty frame");
@@ -1,6 +1,6 @@
 use mediapipe::*;
 use opencv::prelude::*;
-use opencv::{highgui, imgproc, videoio, Result};
+use opencv::{highgui, videoio, Result};
 
 pub fn pose_estimation() -> Result<()> {
     let window = "video capture";
@@ -17,25 +17,25 @@ pub fn pose_estimation() -> Result<()> {
     cap.set(videoio::CAP_PROP_FPS, 30.0)?;
 
     let mut detector = pose::PoseDetector::default();
+    detector.set_pixel_format(PixelFormat::Bgr);
 
     let mut raw_frame = Mat::default();
-    let mut rgb_frame = Mat::default();
     let mut flip_frame = Mat::default();
     loop {
         cap.read(&mut raw_frame)?;
 
         let size = raw_frame.size()?;
         if size.width > 0 && !raw_frame.empty() {
-            imgproc::cvt_color(&raw_frame, &mut rgb_frame, imgproc::COLOR_BGR2RGB, 0)?;
-            opencv::core::flip(&rgb_frame, &mut flip_frame, 1)?; // horizontal
+            opencv::core::flip(&raw_frame, &mut flip_frame, 1)?; // horizontal
 
             println!("processing");
-            let result = detector.process(&mut rgb_frame);
+            let result = detector.process(&flip_frame);
 
-            highgui::imshow(window, &mut rgb_frame)?;
+            highgui::imshow(window, &flip_frame)?;
 
             if let Some(pose) = result {
-                println!("LANDMARK: {} {} {}", pose.data[0].x, pose.data[0].y, pose.data[0].z);
+                let landmark = pose.landmarks.data[0];
+                println!("LANDMARK: {} {} {}", landmark.x, landmark.y, landmark.z);
             }
         } else {
             println!("WARN: Skip empty frame");
@@ -17,22 +17,25 @@ fn segmentation() -> Result<()> {
     cap.set(videoio::CAP_PROP_FPS, 30.0)?;
 
     let mut detector = segmentation::Segmentor::default();
+    detector.set_pixel_format(PixelFormat::Bgr);
 
     let mut raw_frame = Mat::default();
-    let mut rgb_frame = Mat::default();
     let mut flip_frame = Mat::default();
+    let mut out_frame = Mat::default();
     loop {
         cap.read(&mut raw_frame)?;
 
         let size = raw_frame.size()?;
         if size.width > 0 && !raw_frame.empty() {
-            imgproc::cvt_color(&raw_frame, &mut rgb_frame, imgproc::COLOR_BGR2RGB, 0)?;
-            opencv::core::flip(&rgb_frame, &mut flip_frame, 1)?; // horizontal
+            opencv::core::flip(&raw_frame, &mut flip_frame, 1)?; // horizontal
 
             println!("processing");
-            let mut result = detector.process(&mut flip_frame);
+            let result = detector.process(&flip_frame);
             println!("processed");
-            highgui::imshow(window, &mut result)?;
+            if !result.empty() {
+                imgproc::cvt_color(&result, &mut out_frame, imgproc::COLOR_RGB2BGR, 0)?;
+                highgui::imshow(window, &out_frame)?;
+            }
         } else {
             println!("WARN: Skip empty frame");
         }
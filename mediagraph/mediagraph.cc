@@ -0,0 +1,858 @@
+#include "mediagraph/mediagraph.h"
+
+#include <algorithm>
+#include <cstdint>
+#include <limits>
+#include <map>
+#include <memory>
+#include <mutex>
+#include <string>
+#include <utility>
+#include <vector>
+
+#include "absl/flags/declare.h"
+#include "absl/flags/flag.h"
+#include "absl/status/status.h"
+#include "absl/status/statusor.h"
+#include "absl/strings/match.h"
+#include "absl/strings/str_cat.h"
+#include "absl/strings/str_split.h"
+#include "absl/strings/string_view.h"
+#include "google/protobuf/any.pb.h"
+#include "google/protobuf/descriptor.h"
+#include "google/protobuf/message.h"
+#include "google/protobuf/text_format.h"
+#include "mediapipe/framework/calculator_framework.h"
+#include "mediapipe/framework/deps/file_path.h"
+#include "mediapipe/framework/formats/classification.pb.h"
+#include "mediapipe/framework/formats/detection.pb.h"
+#include "mediapipe/framework/formats/image.h"
+#include "mediapipe/framework/formats/image_frame.h"
+#include "mediapipe/framework/formats/landmark.pb.h"
+#include "mediapipe/framework/formats/matrix.h"
+#include "mediapipe/framework/formats/matrix_data.pb.h"
+#include "mediapipe/framework/formats/rect.pb.h"
+#include "mediapipe/framework/port/logging.h"
+#include "mediapipe/framework/port/parse_text_proto.h"
+#include "mediapipe/framework/port/status.h"
+#include "mediapipe/framework/resources.h"
+#include "mediapipe/framework/resources_service.h"
+#include "mediapipe/framework/tool/subgraph_expansion.h"
+#include "mediapipe/modules/face_geometry/protos/face_geometry.pb.h"
+#include "mediapipe/tasks/cc/components/containers/proto/classifications.pb.h"
+#include "mediapipe/tasks/cc/components/containers/proto/embeddings.pb.h"
+
+// Defined by mediapipe/util/resource_util, prepended to the model paths of the graphs.
+ABSL_DECLARE_FLAG(std::string, resource_root_dir);
+
+namespace mediagraph {
+
+namespace {
+
+using mediapipe::tasks::components::containers::proto::ClassificationResult;
+using mediapipe::tasks::components::containers::proto::EmbeddingResult;
+
+constexpr char kInputStream[] = "input_video";
+constexpr int kMaxFeatures = std::numeric_limits<uint8_t>::max();
+
+// The status of the last call on this thread, see last_status().
+thread_local int last_code = 0;
+thread_local std::string last_message;
+
+void SetStatus(const absl::Status& status) {
+  last_code = static_cast<int>(status.code());
+  last_message = std::string(status.message());
+}
+
+absl::Status UnsupportedPacket(const mediapipe::Packet& packet, absl::string_view expected) {
+  return absl::InvalidArgumentError(absl::StrCat("expected ", expected, " but got a packet of type ", packet.DebugTypeName()));
+}
+
+// Calls `fn` with the message held by `packet`, or with every message of a std::vector<T>.
+// Returns false if the packet holds neither.
+template <typename T, typename Fn>
+bool ForEach(const mediapipe::Packet& packet, Fn fn) {
+  if (packet.ValidateAsType<T>().ok()) {
+    fn(packet.Get<T>());
+    return true;
+  }
+  if (packet.ValidateAsType<std::vector<T>>().ok()) {
+    for (const T& item : packet.Get<std::vector<T>>()) fn(item);
+    return true;
+  }
+  return false;
+}
+
+// Serves the files of a Resources descriptor from memory and reads the others from its root
+// directory.
+class GraphResources : public mediapipe::Resources {
+ public:
+  explicit GraphResources(const ::mediagraph::Resources& resources) : fallback_(mediapipe::CreateDefaultResources()) {
+    if (resources.root_dir != nullptr) root_dir_ = resources.root_dir;
+    for (uint32_t i = 0; i < resources.num_files; ++i) {
+      const Resource& file = resources.files[i];
+      files_[file.path] = std::string(reinterpret_cast<const char*>(file.data), file.size);
+    }
+  }
+
+  using mediapipe::Resources::Get;
+
+  absl::StatusOr<std::unique_ptr<mediapipe::Resource>> Get(absl::string_view resource_id, const Options& options) const override {
+    auto file = files_.find(resource_id);
+    if (file != files_.end()) return mediapipe::MakeNoCleanupResource(file->second.data(), file->second.size());
+    // Absolute paths, e.g. of user supplied models, are read as they are.
+    if (root_dir_.empty() || absl::StartsWith(resource_id, "/")) return fallback_->Get(resource_id, options);
+    return fallback_->Get(mediapipe::file::JoinPath(root_dir_, resource_id), options);
+  }
+
+ private:
+  std::string root_dir_;
+  std::map<std::string, std::string, std::less<>> files_;
+  std::unique_ptr<mediapipe::Resources> fallback_;
+};
+
+// Whether `node` is named by `name`, its calculator type or the end of its expanded name.
+bool NodeMatches(const mediapipe::CalculatorGraphConfig::Node& node, absl::string_view name) {
+  return node.calculator() == name || node.name() == name || absl::EndsWith(node.name(), absl::StrCat("__", name));
+}
+
+// Merges `field: value` into the options of `node` that have `field`, `field` may be a dotted
+// path into nested messages.
+absl::Status SetNodeOption(mediapipe::CalculatorGraphConfig::Node& node, absl::string_view field, absl::string_view value) {
+  std::vector<std::string> path = absl::StrSplit(field, '.');
+  std::string text = absl::StrCat(path.back(), ": ", value);
+  for (auto it = path.rbegin() + 1; it != path.rend(); ++it) text = absl::StrCat(*it, " { ", text, " }");
+
+  auto merge = [&](google::protobuf::Message& options) {
+    return options.GetDescriptor()->FindFieldByName(path.front()) != nullptr && google::protobuf::TextFormat::MergeFromString(text, &options);
+  };
+
+  // Options set as extensions of CalculatorOptions.
+  if (node.has_options()) {
+    mediapipe::CalculatorOptions* options = node.mutable_options();
+    const google::protobuf::Reflection* reflection = options->GetReflection();
+    std::vector<const google::protobuf::FieldDescriptor*> fields;
+    reflection->ListFields(*options, &fields);
+    for (const google::protobuf::FieldDescriptor* extension : fields) {
+      if (!extension->is_extension() || extension->is_repeated() || extension->cpp_type() != google::protobuf::FieldDescriptor::CPPTYPE_MESSAGE) continue;
+      if (merge(*reflection->MutableMessage(options, extension))) return absl::OkStatus();
+    }
+  }
+
+  // Options set as google.protobuf.Any.
+  for (google::protobuf::Any& any : *node.mutable_node_options()) {
+    const std::string type_name = any.type_url().substr(any.type_url().rfind('/') + 1);
+    const google::protobuf::Descriptor* descriptor = google::protobuf::DescriptorPool::generated_pool()->FindMessageTypeByName(type_name);
+    if (descriptor == nullptr) continue;
+    std::unique_ptr<google::protobuf::Message> options(google::protobuf::MessageFactory::generated_factory()->GetPrototype(descriptor)->New());
+    if (!any.UnpackTo(options.get()) || !merge(*options)) continue;
+    any.PackFrom(*options);
+    return absl::OkStatus();
+  }
+
+  return absl::InvalidArgumentError(absl::StrCat("node ", node.name(), " has no option ", field, " that takes ", value));
+}
+
+// Sets `option` on every node of the expanded `config` it names.
+absl::Status SetCalculatorOption(mediapipe::CalculatorGraphConfig& config, const CalculatorOption& option) {
+  int matches = 0;
+  for (mediapipe::CalculatorGraphConfig::Node& node : *config.mutable_node()) {
+    if (!NodeMatches(node, option.node)) continue;
+    MP_RETURN_IF_ERROR(SetNodeOption(node, option.field, option.value));
+    ++matches;
+  }
+  if (matches == 0) return absl::InvalidArgumentError(absl::StrCat("graph has no node ", option.node));
+  return absl::OkStatus();
+}
+
+// Parses `graph_config`, sets the calculator options and initializes `graph` with it. The model
+// files are read from `resources` if it is not null.
+absl::Status InitializeGraph(mediapipe::CalculatorGraph& graph, const char* graph_config, const CalculatorOption* options, uint8_t num_options, const Resources* resources) {
+  mediapipe::CalculatorGraphConfig config;
+  if (graph_config == nullptr || !mediapipe::ParseTextProto(graph_config, &config)) {
+    return absl::InvalidArgumentError("graph config could not be parsed");
+  }
+  if (num_options > 0) {
+    // The nodes of subgraphs only exist once they are expanded.
+    MP_RETURN_IF_ERROR(mediapipe::tool::ExpandSubgraphs(&config));
+    for (uint8_t i = 0; i < num_options; ++i) MP_RETURN_IF_ERROR(SetCalculatorOption(config, options[i]));
+  }
+  if (resources != nullptr) {
+    MP_RETURN_IF_ERROR(graph.SetServiceObject(mediapipe::kResourcesService, std::make_shared<GraphResources>(*resources)));
+  }
+  return graph.Initialize(config);
+}
+
+// Stops a running graph, errors are logged as there is no one left to report them to.
+void StopGraph(mediapipe::CalculatorGraph& graph) {
+  absl::Status status = graph.CloseAllInputStreams();
+  if (status.ok()) status = graph.WaitUntilDone();
+  if (!status.ok()) LOG(ERROR) << "mediagraph: failed to stop graph: " << status;
+}
+
+// Copies a packed RGB frame into a packet with the given timestamp.
+absl::StatusOr<mediapipe::Packet> FramePacket(const uint8_t* data, int width, int height, int64_t timestamp_us) {
+  if (data == nullptr || width <= 0 || height <= 0) {
+    return absl::InvalidArgumentError(absl::StrCat("invalid frame of ", width, "x", height));
+  }
+  auto frame = std::make_unique<mediapipe::ImageFrame>();
+  frame->CopyPixelData(mediapipe::ImageFormat::SRGB, width, height, width * 3, data, mediapipe::ImageFrame::kDefaultAlignmentBoundary);
+  return mediapipe::Adopt(frame.release()).At(mediapipe::Timestamp(timestamp_us));
+}
+
+// The converted contents of the last packet read from an output. The Packet handed out points
+// into it and into the MediaPipe packet it holds on to.
+struct PacketStorage {
+  mediapipe::Packet packet;
+  std::shared_ptr<mediapipe::ImageFrame> frame;
+  std::vector<Landmark> landmarks;
+  std::vector<int> list_sizes;
+  std::vector<Classification> classifications;
+  std::vector<Detection> detections;
+  std::vector<Keypoint> keypoints;
+  std::vector<Rect> rects;
+  std::vector<float> floats;
+};
+
+// Points `image` at the pixels of an ImageFrame or Image packet. `frame` keeps them alive as long
+// as the packet is held.
+absl::Status ToImage(const mediapipe::Packet& packet, std::shared_ptr<mediapipe::ImageFrame>& frame, Image& image) {
+  if (packet.ValidateAsType<mediapipe::ImageFrame>().ok()) {
+    // The packet owns the frame, the aliasing pointer doesn't.
+    frame = std::shared_ptr<mediapipe::ImageFrame>(std::shared_ptr<mediapipe::ImageFrame>(), const_cast<mediapipe::ImageFrame*>(&packet.Get<mediapipe::ImageFrame>()));
+  } else if (packet.ValidateAsType<mediapipe::Image>().ok()) {
+    frame = packet.Get<mediapipe::Image>().GetImageFrameSharedPtr();
+  } else {
+    return UnsupportedPacket(packet, "an ImageFrame or Image");
+  }
+
+  const int channels = frame->NumberOfChannels();
+  const bool is_float = frame->ByteDepth() == 4;
+  if (!(frame->ByteDepth() == 1 && (channels == 1 || channels == 3 || channels == 4)) && !(is_float && channels == 1)) {
+    return absl::InvalidArgumentError(absl::StrCat("unsupported image format ", frame->Format()));
+  }
+  image.data = const_cast<uint8_t*>(frame->PixelData());
+  image.width = frame->Width();
+  image.height = frame->Height();
+  image.width_step = frame->WidthStep();
+  image.channels = channels;
+  image.is_float = is_float;
+  return absl::OkStatus();
+}
+
+// Copies the MatrixData, or the pose transform matrices of the FaceGeometry, held by `packet`
+// into `storage`, column-major.
+absl::Status ToMatrices(const mediapipe::Packet& packet, PacketStorage& storage, Packet& out) {
+  absl::Status status;
+  auto append = [&](const mediapipe::MatrixData& matrix) {
+    if (!status.ok()) return;
+    if (out.num_lists == 0) {
+      out.rows = matrix.rows();
+      out.cols = matrix.cols();
+    } else if (matrix.rows() != out.rows || matrix.cols() != out.cols) {
+      status = absl::InvalidArgumentError("matrices of different sizes in one packet");
+      return;
+    }
+    if (matrix.packed_data_size() != matrix.rows() * matrix.cols()) {
+      status = absl::InvalidArgumentError(absl::StrCat("matrix of ", matrix.rows(), "x", matrix.cols(), " has ", matrix.packed_data_size(), " elements"));
+      return;
+    }
+    const bool row_major = matrix.layout() == mediapipe::MatrixData::ROW_MAJOR;
+    for (int col = 0; col < matrix.cols(); ++col) {
+      for (int row = 0; row < matrix.rows(); ++row) {
+        storage.floats.push_back(matrix.packed_data(row_major ? row * matrix.cols() + col : col * matrix.rows() + row));
+      }
+    }
+    ++out.num_lists;
+  };
+
+  if (!ForEach<mediapipe::MatrixData>(packet, append) &&
+      !ForEach<mediapipe::face_geometry::FaceGeometry>(packet, [&](const mediapipe::face_geometry::FaceGeometry& geometry) { append(geometry.pose_transform_matrix()); })) {
+    return UnsupportedPacket(packet, "matrices");
+  }
+  return status;
+}
+
+// Converts a packet read from a graph, `storage` holds the data `out` points to.
+absl::Status ToPacket(const mediapipe::Packet& packet, PacketType type, PacketStorage& storage, Packet& out) {
+  storage = PacketStorage();
+  storage.packet = packet;
+  out = Packet();
+  out.type = type;
+  out.timestamp_us = packet.Timestamp().Microseconds();
+
+  switch (type) {
+    case PacketType::IMAGE:
+      return ToImage(packet, storage.frame, out.image);
+
+    case PacketType::LANDMARKS: {
+      auto append = [&](const auto& list) {
+        storage.list_sizes.push_back(list.landmark_size());
+        for (const auto& l : list.landmark()) storage.landmarks.push_back({l.x(), l.y(), l.z(), l.visibility(), l.presence()});
+      };
+      if (!ForEach<mediapipe::NormalizedLandmarkList>(packet, append) && !ForEach<mediapipe::LandmarkList>(packet, append)) {
+        return UnsupportedPacket(packet, "landmark lists");
+      }
+      out.landmarks = storage.landmarks.data();
+      break;
+    }
+
+    case PacketType::DETECTIONS: {
+      std::vector<const mediapipe::Detection*> detections;
+      if (!ForEach<mediapipe::Detection>(packet, [&](const mediapipe::Detection& d) { detections.push_back(&d); })) {
+        return UnsupportedPacket(packet, "detections");
+      }
+      // Keypoints are collected first, so their buffer doesn't move while detections point into it.
+      for (const mediapipe::Detection* d : detections) {
+        for (const auto& k : d->location_data().relative_keypoints()) storage.keypoints.push_back({k.x(), k.y()});
+      }
+      size_t offset = 0;
+      for (const mediapipe::Detection* d : detections) {
+        const auto& box = d->location_data().relative_bounding_box();
+        const int num_keypoints = d->location_data().relative_keypoints_size();
+        storage.detections.push_back({
+            d->score_size() > 0 ? d->score(0) : 0.0f,
+            d->label_id_size() > 0 ? d->label_id(0) : 0,
+            d->label_size() > 0 ? d->label(0).c_str() : nullptr,
+            {box.xmin(), box.ymin(), box.width(), box.height()},
+            storage.keypoints.data() + offset,
+            num_keypoints,
+        });
+        offset += num_keypoints;
+      }
+      out.detections = storage.detections.data();
+      out.num_detections = storage.detections.size();
+      break;
+    }
+
+    case PacketType::CLASSIFICATIONS: {
+      auto append = [&](const mediapipe::ClassificationList& list) {
+        storage.list_sizes.push_back(list.classification_size());
+        for (const auto& c : list.classification()) storage.classifications.push_back({c.index(), c.score(), c.label().c_str(), c.display_name().c_str()});
+      };
+      if (packet.ValidateAsType<ClassificationResult>().ok()) {
+        for (const auto& head : packet.Get<ClassificationResult>().classifications()) append(head.classification_list());
+      } else if (!ForEach<mediapipe::ClassificationList>(packet, append)) {
+        return UnsupportedPacket(packet, "classification lists");
+      }
+      out.classifications = storage.classifications.data();
+      break;
+    }
+
+    case PacketType::RECTS:
+      if (!ForEach<mediapipe::NormalizedRect>(packet, [&](const mediapipe::NormalizedRect& r) { storage.rects.push_back({r.x_center(), r.y_center(), r.width(), r.height(), r.rotation()}); })) {
+        return UnsupportedPacket(packet, "rects");
+      }
+      out.rects = storage.rects.data();
+      out.num_rects = storage.rects.size();
+      break;
+
+    case PacketType::FLOATS:
+      if (packet.ValidateAsType<std::vector<float>>().ok()) {
+        storage.floats = packet.Get<std::vector<float>>();
+      } else if (packet.ValidateAsType<EmbeddingResult>().ok()) {
+        const EmbeddingResult& result = packet.Get<EmbeddingResult>();
+        if (result.embeddings_size() == 0) return absl::InvalidArgumentError("embedding result without embeddings");
+        const auto& values = result.embeddings(0).float_embedding().values();
+        storage.floats.assign(values.begin(), values.end());
+      } else {
+        return UnsupportedPacket(packet, "floats");
+      }
+      out.floats = storage.floats.data();
+      out.num_floats = storage.floats.size();
+      break;
+
+    case PacketType::MATRIX: {
+      if (!packet.ValidateAsType<mediapipe::Matrix>().ok()) return UnsupportedPacket(packet, "a matrix");
+      const mediapipe::Matrix& matrix = packet.Get<mediapipe::Matrix>();
+      // Matrix is column-major, like the packet layout.
+      storage.floats.assign(matrix.data(), matrix.data() + matrix.size());
+      out.floats = storage.floats.data();
+      out.num_floats = storage.floats.size();
+      out.rows = matrix.rows();
+      out.cols = matrix.cols();
+      break;
+    }
+
+    case PacketType::MATRICES:
+      MP_RETURN_IF_ERROR(ToMatrices(packet, storage, out));
+      out.floats = storage.floats.data();
+      out.num_floats = storage.floats.size();
+      break;
+
+    default:
+      return absl::InvalidArgumentError(absl::StrCat("unknown packet type ", static_cast<uint32_t>(type)));
+  }
+
+  if (!storage.list_sizes.empty()) {
+    out.list_sizes = storage.list_sizes.data();
+    out.num_lists = storage.list_sizes.size();
+  }
+  return absl::OkStatus();
+}
+
+// Copies the list at `index` of `items`, split by `sizes`, into a message with `add`.
+template <typename T, typename Add>
+void CopyList(const T* items, const int* sizes, int index, Add add) {
+  int offset = 0;
+  for (int i = 0; i < index; ++i) offset += sizes[i];
+  for (int i = 0; i < sizes[index]; ++i) add(items[offset + i]);
+}
+
+// Builds a message per list, sent as one message if there is a single list.
+template <typename List, typename Fill>
+mediapipe::Packet ListPacket(int num_lists, Fill fill) {
+  if (num_lists == 1) {
+    List list;
+    fill(0, list);
+    return mediapipe::MakePacket<List>(std::move(list));
+  }
+  std::vector<List> lists(num_lists);
+  for (int i = 0; i < num_lists; ++i) fill(i, lists[i]);
+  return mediapipe::MakePacket<std::vector<List>>(std::move(lists));
+}
+
+// Copies a packet sent to a graph into a MediaPipe packet.
+absl::StatusOr<mediapipe::Packet> FromPacket(const Packet& in) {
+  mediapipe::Packet packet;
+
+  switch (in.type) {
+    case PacketType::IMAGE: {
+      const Image& image = in.image;
+      mediapipe::ImageFormat::Format format;
+      if (image.is_float && image.channels == 1) {
+        format = mediapipe::ImageFormat::VEC32F1;
+      } else if (!image.is_float && image.channels == 1) {
+        format = mediapipe::ImageFormat::GRAY8;
+      } else if (!image.is_float && image.channels == 3) {
+        format = mediapipe::ImageFormat::SRGB;
+      } else if (!image.is_float && image.channels == 4) {
+        format = mediapipe::ImageFormat::SRGBA;
+      } else {
+        return absl::InvalidArgumentError(absl::StrCat("unsupported image with ", image.channels, " channels"));
+      }
+      if (image.data == nullptr || image.width <= 0 || image.height <= 0) {
+        return absl::InvalidArgumentError(absl::StrCat("invalid image of ", image.width, "x", image.height));
+      }
+      auto frame = std::make_unique<mediapipe::ImageFrame>();
+      frame->CopyPixelData(format, image.width, image.height, image.width_step, image.data, mediapipe::ImageFrame::kDefaultAlignmentBoundary);
+      packet = mediapipe::Adopt(frame.release());
+      break;
+    }
+
+    case PacketType::LANDMARKS:
+      packet = ListPacket<mediapipe::NormalizedLandmarkList>(in.num_lists, [&](int i, mediapipe::NormalizedLandmarkList& list) {
+        CopyList(in.landmarks, in.list_sizes, i, [&](const Landmark& l) {
+          mediapipe::NormalizedLandmark* landmark = list.add_landmark();
+          landmark->set_x(l.x);
+          landmark->set_y(l.y);
+          landmark->set_z(l.z);
+          landmark->set_visibility(l.visibility);
+          landmark->set_presence(l.presence);
+        });
+      });
+      break;
+
+    case PacketType::DETECTIONS: {
+      std::vector<mediapipe::Detection> detections(in.num_detections);
+      for (int i = 0; i < in.num_detections; ++i) {
+        const Detection& d = in.detections[i];
+        detections[i].add_score(d.score);
+        detections[i].add_label_id(d.label_id);
+        if (d.label != nullptr && d.label[0] != '\0') detections[i].add_label(d.label);
+        mediapipe::LocationData* location = detections[i].mutable_location_data();
+        location->set_format(mediapipe::LocationData::RELATIVE_BOUNDING_BOX);
+        location->mutable_relative_bounding_box()->set_xmin(d.box.xmin);
+        location->mutable_relative_bounding_box()->set_ymin(d.box.ymin);
+        location->mutable_relative_bounding_box()->set_width(d.box.width);
+        location->mutable_relative_bounding_box()->set_height(d.box.height);
+        for (int k = 0; k < d.num_keypoints; ++k) {
+          auto* keypoint = location->add_relative_keypoints();
+          keypoint->set_x(d.keypoints[k].x);
+          keypoint->set_y(d.keypoints[k].y);
+        }
+      }
+      packet = mediapipe::MakePacket<std::vector<mediapipe::Detection>>(std::move(detections));
+      break;
+    }
+
+    case PacketType::CLASSIFICATIONS:
+      packet = ListPacket<mediapipe::ClassificationList>(in.num_lists, [&](int i, mediapipe::ClassificationList& list) {
+        CopyList(in.classifications, in.list_sizes, i, [&](const Classification& c) {
+          mediapipe::Classification* classification = list.add_classification();
+          classification->set_index(c.index);
+          classification->set_score(c.score);
+          if (c.label != nullptr) classification->set_label(c.label);
+          if (c.display_name != nullptr) classification->set_display_name(c.display_name);
+        });
+      });
+      break;
+
+    case PacketType::RECTS: {
+      auto to_rect = [](const Rect& r) {
+        mediapipe::NormalizedRect rect;
+        rect.set_x_center(r.x_center);
+        rect.set_y_center(r.y_center);
+        rect.set_width(r.width);
+        rect.set_height(r.height);
+        rect.set_rotation(r.rotation);
+        return rect;
+      };
+      if (in.num_rects == 1) {
+        packet = mediapipe::MakePacket<mediapipe::NormalizedRect>(to_rect(in.rects[0]));
+      } else {
+        std::vector<mediapipe::NormalizedRect> rects;
+        for (int i = 0; i < in.num_rects; ++i) rects.push_back(to_rect(in.rects[i]));
+        packet = mediapipe::MakePacket<std::vector<mediapipe::NormalizedRect>>(std::move(rects));
+      }
+      break;
+    }
+
+    case PacketType::FLOATS:
+      packet = mediapipe::MakePacket<std::vector<float>>(in.floats, in.floats + std::max(in.num_floats, 0));
+      break;
+
+    case PacketType::MATRIX: {
+      if (in.num_floats != in.rows * in.cols) {
+        return absl::InvalidArgumentError(absl::StrCat("matrix of ", in.rows, "x", in.cols, " has ", in.num_floats, " elements"));
+      }
+      mediapipe::Matrix matrix(in.rows, in.cols);
+      std::copy(in.floats, in.floats + in.num_floats, matrix.data());
+      packet = mediapipe::MakePacket<mediapipe::Matrix>(std::move(matrix));
+      break;
+    }
+
+    case PacketType::MATRICES: {
+      if (in.num_floats != in.num_lists * in.rows * in.cols) {
+        return absl::InvalidArgumentError(absl::StrCat(in.num_lists, " matrices of ", in.rows, "x", in.cols, " have ", in.num_floats, " elements"));
+      }
+      std::vector<mediapipe::MatrixData> matrices(in.num_lists);
+      for (int i = 0; i < in.num_lists; ++i) {
+        matrices[i].set_rows(in.rows);
+        matrices[i].set_cols(in.cols);
+        const float* data = in.floats + i * in.rows * in.cols;
+        for (int j = 0; j < in.rows * in.cols; ++j) matrices[i].add_packed_data(data[j]);
+      }
+      packet = mediapipe::MakePacket<std::vector<mediapipe::MatrixData>>(std::move(matrices));
+      break;
+    }
+
+    default:
+      return absl::InvalidArgumentError(absl::StrCat("unknown packet type ", static_cast<uint32_t>(in.type)));
+  }
+
+  return packet.At(mediapipe::Timestamp(in.timestamp_us));
+}
+
+// Number of landmarks of each feature of an output, 0 for outputs read with GetPacket.
+size_t NumLandmarks(FeatureType type) {
+  switch (type) {
+    case FeatureType::FACE:
+    case FeatureType::FACES:
+      return 478;
+    case FeatureType::HAND:
+    case FeatureType::HANDS:
+    case FeatureType::HANDS_WORLD:
+      return 21;
+    case FeatureType::POSE:
+    case FeatureType::POSES:
+    case FeatureType::POSE_WORLD:
+    case FeatureType::POSES_WORLD:
+      return 33;
+    default:
+      return 0;
+  }
+}
+
+// The packet type of an output read with GetPacket.
+PacketType PacketTypeOf(FeatureType type) {
+  switch (type) {
+    case FeatureType::CLASSIFICATIONS:
+      return PacketType::CLASSIFICATIONS;
+    case FeatureType::IMAGE:
+      return PacketType::IMAGE;
+    case FeatureType::DETECTIONS:
+      return PacketType::DETECTIONS;
+    case FeatureType::RECTS:
+      return PacketType::RECTS;
+    case FeatureType::MATRICES:
+      return PacketType::MATRICES;
+    case FeatureType::FLOATS:
+      return PacketType::FLOATS;
+    default:
+      return PacketType::LANDMARKS;
+  }
+}
+
+// Appends exactly `num_landmarks` landmarks of `list`. Shorter lists are padded with zeros, so
+// the features of an output keep a fixed stride, e.g. face meshes without refined landmarks.
+template <typename List>
+void AppendLandmarks(const List& list, size_t num_landmarks, std::vector<Landmark>& landmarks) {
+  for (size_t i = 0; i < num_landmarks; ++i) {
+    if (i < static_cast<size_t>(list.landmark_size())) {
+      const auto& l = list.landmark(i);
+      landmarks.push_back({l.x(), l.y(), l.z(), l.visibility(), l.presence()});
+    } else {
+      landmarks.push_back({});
+    }
+  }
+}
+
+}  // namespace
+
+Status last_status() {
+  return {last_code, last_code == 0 ? nullptr : last_message.c_str()};
+}
+
+void set_resource_root_dir(const char* path) {
+  absl::SetFlag(&FLAGS_resource_root_dir, path);
+  SetStatus(absl::OkStatus());
+}
+
+struct Detector::Impl {
+  mediapipe::CalculatorGraph graph;
+  std::vector<FeatureType> types;
+  // The last packet of each output, written by the output stream observers.
+  std::mutex mutex;
+  std::vector<mediapipe::Packet> packets;
+  std::vector<PacketStorage> storage;
+  std::vector<Landmark> landmarks;
+
+  absl::Status Start(const char* graph_config, const Output* outputs, uint8_t num_outputs, const CalculatorOption* options, uint8_t num_options, const Resources* resources) {
+    MP_RETURN_IF_ERROR(InitializeGraph(graph, graph_config, options, num_options, resources));
+    for (uint8_t i = 0; i < num_outputs; ++i) types.push_back(outputs[i].type);
+    packets.resize(num_outputs);
+    storage.resize(num_outputs);
+    for (uint8_t i = 0; i < num_outputs; ++i) {
+      MP_RETURN_IF_ERROR(graph.ObserveOutputStream(outputs[i].name, [this, i](const mediapipe::Packet& packet) {
+        std::lock_guard<std::mutex> lock(mutex);
+        packets[i] = packet;
+        return absl::OkStatus();
+      }));
+    }
+    return graph.StartRun({});
+  }
+
+  absl::Status Process(const uint8_t* data, int width, int height, int64_t timestamp_us, uint8_t* num_features) {
+    std::fill(num_features, num_features + types.size(), 0);
+    landmarks.clear();
+    {
+      std::lock_guard<std::mutex> lock(mutex);
+      std::fill(packets.begin(), packets.end(), mediapipe::Packet());
+    }
+
+    absl::StatusOr<mediapipe::Packet> frame = FramePacket(data, width, height, timestamp_us);
+    MP_RETURN_IF_ERROR(frame.status());
+    MP_RETURN_IF_ERROR(graph.AddPacketToInputStream(kInputStream, *std::move(frame)));
+    MP_RETURN_IF_ERROR(graph.WaitUntilIdle());
+
+    std::lock_guard<std::mutex> lock(mutex);
+    for (size_t i = 0; i < types.size(); ++i) {
+      if (packets[i].IsEmpty()) continue;
+      const size_t num_landmarks = NumLandmarks(types[i]);
+      if (num_landmarks == 0) {
+        num_features[i] = 1;
+        continue;
+      }
+      int count = 0;
+      auto append = [&](const auto& list) {
+        if (count == kMaxFeatures) return;
+        AppendLandmarks(list, num_landmarks, landmarks);
+        ++count;
+      };
+      if (!ForEach<mediapipe::NormalizedLandmarkList>(packets[i], append) && !ForEach<mediapipe::LandmarkList>(packets[i], append)) {
+        return UnsupportedPacket(packets[i], "landmark lists");
+      }
+      num_features[i] = count;
+    }
+    return absl::OkStatus();
+  }
+
+  absl::StatusOr<bool> GetPacket(uint8_t output, Packet& packet) {
+    if (output >= types.size() || NumLandmarks(types[output]) > 0) {
+      return absl::InvalidArgumentError(absl::StrCat("output ", static_cast<int>(output), " has no packets to read"));
+    }
+    std::lock_guard<std::mutex> lock(mutex);
+    if (packets[output].IsEmpty()) return false;
+    MP_RETURN_IF_ERROR(ToPacket(packets[output], PacketTypeOf(types[output]), storage[output], packet));
+    return true;
+  }
+};
+
+Detector::Detector(Impl* impl) : impl_(impl) {}
+
+Detector::~Detector() {
+  StopGraph(impl_->graph);
+  delete impl_;
+}
+
+Detector* Detector::Create(const char* graph_config, const Output* outputs, uint8_t num_outputs) {
+  return CreateWithOptions(graph_config, outputs, num_outputs, nullptr, 0, nullptr);
+}
+
+Detector* Detector::CreateWithOptions(const char* graph_config, const Output* outputs, uint8_t num_outputs, const CalculatorOption* options, uint8_t num_options,
+                                      const Resources* resources) {
+  auto impl = std::make_unique<Impl>();
+  absl::Status status = impl->Start(graph_config, outputs, num_outputs, options, num_options, resources);
+  SetStatus(status);
+  if (!status.ok()) return nullptr;
+  return new Detector(impl.release());
+}
+
+void Detector::Destroy(Detector* detector) {
+  delete detector;
+  SetStatus(absl::OkStatus());
+}
+
+Landmark* Detector::Process(uint8_t* data, int width, int height, int64_t timestamp_us, uint8_t* num_features) {
+  absl::Status status = impl_->Process(data, width, height, timestamp_us, num_features);
+  SetStatus(status);
+  if (!status.ok()) return nullptr;
+  return impl_->landmarks.data();
+}
+
+bool Detector::GetPacket(uint8_t output, Packet* packet) {
+  absl::StatusOr<bool> available = impl_->GetPacket(output, *packet);
+  SetStatus(available.status());
+  return available.ok() && *available;
+}
+
+struct Effect::Impl {
+  mediapipe::CalculatorGraph graph;
+  // The last rendered frame, written by the output stream observer.
+  std::mutex mutex;
+  mediapipe::Packet output;
+  std::shared_ptr<mediapipe::ImageFrame> frame;
+
+  absl::Status Start(const char* graph_config, const char* output_node) {
+    MP_RETURN_IF_ERROR(InitializeGraph(graph, graph_config, nullptr, 0, nullptr));
+    MP_RETURN_IF_ERROR(graph.ObserveOutputStream(output_node, [this](const mediapipe::Packet& packet) {
+      std::lock_guard<std::mutex> lock(mutex);
+      output = packet;
+      return absl::OkStatus();
+    }));
+    return graph.StartRun({});
+  }
+
+  absl::Status Process(const uint8_t* data, int width, int height, int64_t timestamp_us, Image& image) {
+    image = Image();
+    {
+      std::lock_guard<std::mutex> lock(mutex);
+      output = mediapipe::Packet();
+    }
+
+    absl::StatusOr<mediapipe::Packet> input = FramePacket(data, width, height, timestamp_us);
+    MP_RETURN_IF_ERROR(input.status());
+    MP_RETURN_IF_ERROR(graph.AddPacketToInputStream(kInputStream, *std::move(input)));
+    MP_RETURN_IF_ERROR(graph.WaitUntilIdle());
+
+    std::lock_guard<std::mutex> lock(mutex);
+    if (output.IsEmpty()) return absl::OkStatus();
+    return ToImage(output, frame, image);
+  }
+};
+
+Effect::Effect(Impl* impl) : impl_(impl) {}
+
+Effect::~Effect() {
+  StopGraph(impl_->graph);
+  delete impl_;
+}
+
+Effect* Effect::Create(const char* graph_config, const char* output_node) {
+  auto impl = std::make_unique<Impl>();
+  absl::Status status = impl->Start(graph_config, output_node);
+  SetStatus(status);
+  if (!status.ok()) return nullptr;
+  return new Effect(impl.release());
+}
+
+void Effect::Destroy(Effect* effect) {
+  delete effect;
+  SetStatus(absl::OkStatus());
+}
+
+Image Effect::Process(uint8_t* data, int width, int height, int64_t timestamp_us) {
+  Image image = {};
+  SetStatus(impl_->Process(data, width, height, timestamp_us, image));
+  return image;
+}
+
+struct Graph::Impl {
+  mediapipe::CalculatorGraph graph;
+  std::vector<PacketType> input_types;
+  std::vector<std::string> input_names;
+  std::vector<PacketType> output_types;
+  std::vector<std::unique_ptr<mediapipe::OutputStreamPoller>> pollers;
+  std::vector<PacketStorage> storage;
+
+  absl::Status Start(const char* graph_config, const Stream* in, uint8_t num_inputs, const Stream* out, uint8_t num_outputs) {
+    MP_RETURN_IF_ERROR(InitializeGraph(graph, graph_config, nullptr, 0, nullptr));
+    for (uint8_t i = 0; i < num_inputs; ++i) {
+      input_types.push_back(in[i].type);
+      input_names.push_back(in[i].name);
+    }
+    for (uint8_t i = 0; i < num_outputs; ++i) {
+      absl::StatusOr<mediapipe::OutputStreamPoller> poller = graph.AddOutputStreamPoller(out[i].name);
+      MP_RETURN_IF_ERROR(poller.status());
+      pollers.push_back(std::make_unique<mediapipe::OutputStreamPoller>(*std::move(poller)));
+      output_types.push_back(out[i].type);
+    }
+    storage.resize(num_outputs);
+    return graph.StartRun({});
+  }
+
+  absl::Status AddPacket(uint8_t input, const Packet& packet) {
+    if (input >= input_types.size()) return absl::InvalidArgumentError(absl::StrCat("no input ", static_cast<int>(input)));
+    if (packet.type != input_types[input]) return absl::InvalidArgumentError(absl::StrCat("input ", input_names[input], " takes packets of another type"));
+    absl::StatusOr<mediapipe::Packet> converted = FromPacket(packet);
+    MP_RETURN_IF_ERROR(converted.status());
+    return graph.AddPacketToInputStream(input_names[input], *std::move(converted));
+  }
+
+  absl::StatusOr<bool> Poll(uint8_t output, bool wait, Packet& packet) {
+    if (output >= pollers.size()) return absl::InvalidArgumentError(absl::StrCat("no output ", static_cast<int>(output)));
+    mediapipe::OutputStreamPoller& poller = *pollers[output];
+    if (!wait && poller.QueueSize() == 0) return false;
+    mediapipe::Packet next;
+    if (!poller.Next(&next)) return false;
+    MP_RETURN_IF_ERROR(ToPacket(next, output_types[output], storage[output], packet));
+    return true;
+  }
+};
+
+Graph::Graph(Impl* impl) : impl_(impl) {}
+
+Graph::~Graph() {
+  StopGraph(impl_->graph);
+  delete impl_;
+}
+
+Graph* Graph::Create(const char* graph_config, const Stream* inputs, uint8_t num_inputs, const Stream* outputs, uint8_t num_outputs) {
+  auto impl = std::make_unique<Impl>();
+  absl::Status status = impl->Start(graph_config, inputs, num_inputs, outputs, num_outputs);
+  SetStatus(status);
+  if (!status.ok()) return nullptr;
+  return new Graph(impl.release());
+}
+
+void Graph::Destroy(Graph* graph) {
+  delete graph;
+  SetStatus(absl::OkStatus());
+}
+
+void Graph::AddPacket(uint8_t input, const Packet* packet) {
+  SetStatus(impl_->AddPacket(input, *packet));
+}
+
+bool Graph::Poll(uint8_t output, bool wait, Packet* packet) {
+  absl::StatusOr<bool> available = impl_->Poll(output, wait, *packet);
+  SetStatus(available.status());
+  return available.ok() && *available;
+}
+
+void Graph::WaitUntilIdle() {
+  SetStatus(impl_->graph.WaitUntilIdle());
+}
+
+}  // namespace mediagraph
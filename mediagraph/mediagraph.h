@@ -0,0 +1,291 @@
+// C API of libmediagraph, the MediaPipe graph runner behind the mediapipe crate.
+//
+// The Rust bindings are generated from this header with bindgen, so it only uses plain structs,
+// enums and classes with static factories. Pointers passed into the library are only read during
+// the call. Buffers returned by the library are owned by the graph that returned them and must not
+// be freed by the caller.
+
+#ifndef MEDIAGRAPH_H
+#define MEDIAGRAPH_H
+
+#include <cstddef>
+#include <cstdint>
+
+namespace mediagraph {
+
+// A landmark, normalized to the frame size for image landmarks or in meters for world landmarks.
+struct Landmark {
+  float x;
+  float y;
+  float z;
+  float visibility;
+  float presence;
+};
+
+// The contents of a detector output stream.
+enum class FeatureType : uint32_t {
+  // A NormalizedLandmarkList, or a std::vector of them for the plural types. Face meshes are
+  // returned with 478 landmarks, meshes without the refined iris and lip landmarks are padded
+  // with zeros.
+  FACE,
+  FACES,
+  HAND,
+  HANDS,
+  POSE,
+  POSES,
+  // A ClassificationList or std::vector<ClassificationList>, read with Detector::GetPacket.
+  CLASSIFICATIONS,
+  // A LandmarkList in world coordinates, or a std::vector of them for the plural types.
+  POSE_WORLD,
+  POSES_WORLD,
+  HANDS_WORLD,
+  // An ImageFrame or Image, read with Detector::GetPacket.
+  IMAGE,
+  // A Detection or std::vector<Detection>, read with Detector::GetPacket.
+  DETECTIONS,
+  // A NormalizedRect or std::vector<NormalizedRect>, read with Detector::GetPacket.
+  RECTS,
+  // A std::vector<MatrixData> or std::vector<FaceGeometry>, read with Detector::GetPacket.
+  MATRICES,
+  // A std::vector<float> or EmbeddingResult, read with Detector::GetPacket.
+  FLOATS,
+};
+
+// A detector output, the graph output stream `name` carrying features of type `type`.
+struct Output {
+  FeatureType type;
+  char* name;
+};
+
+// An image frame produced by a graph, 8-bit with 1, 3 or 4 channels or single channel float.
+//
+// The pixel buffer is owned by the graph that returned it, the caller must copy it and must not
+// free it. It stays valid until the next call that returns an image from the same graph and
+// output (Effect::Process, Detector::GetPacket or Graph::Poll), or until the graph is destroyed.
+// `data` is null if there is no image.
+struct Image {
+  uint8_t* data;
+  int width;
+  int height;
+  // Bytes per row, at least `width * channels` times the size of a channel.
+  int width_step;
+  int channels;
+  bool is_float;
+};
+
+// The status of the last call into the library made on the current thread, `code` is an
+// absl::StatusCode. The message is owned by the library and stays valid until the next call on
+// the same thread.
+struct Status {
+  int code;
+  const char* message;
+};
+
+Status last_status();
+
+// Sets the directory model files are read from, for graphs without their own Resources root.
+void set_resource_root_dir(const char* path);
+
+// The type of the packets carried by a Graph stream.
+enum class PacketType : uint32_t {
+  // An ImageFrame, or an Image on output streams.
+  IMAGE,
+  // A NormalizedLandmarkList, or a std::vector of them. Output streams also accept LandmarkList.
+  LANDMARKS,
+  // A Detection or std::vector<Detection>. Input packets are always sent as a vector.
+  DETECTIONS,
+  // A ClassificationList or std::vector<ClassificationList>, or the heads of a
+  // ClassificationResult on output streams.
+  CLASSIFICATIONS,
+  // A NormalizedRect or std::vector<NormalizedRect>.
+  RECTS,
+  // A std::vector<float>, or the float embedding of the first head of an EmbeddingResult on
+  // output streams.
+  FLOATS,
+  // A Matrix.
+  MATRIX,
+  // A std::vector<MatrixData>, or the pose transform matrices of a std::vector<FaceGeometry> on
+  // output streams.
+  MATRICES,
+};
+
+// A graph stream, `name` is the stream name in the graph config.
+struct Stream {
+  PacketType type;
+  const char* name;
+};
+
+struct BoundingBox {
+  float xmin;
+  float ymin;
+  float width;
+  float height;
+};
+
+struct Keypoint {
+  float x;
+  float y;
+};
+
+// A detection with its best scoring label, in normalized image coordinates.
+struct Detection {
+  float score;
+  int label_id;
+  const char* label;
+  BoundingBox box;
+  const Keypoint* keypoints;
+  int num_keypoints;
+};
+
+struct Classification {
+  int index;
+  float score;
+  const char* label;
+  const char* display_name;
+};
+
+// A rotated rectangle in normalized image coordinates, `rotation` is in radians.
+struct Rect {
+  float x_center;
+  float y_center;
+  float width;
+  float height;
+  float rotation;
+};
+
+// A packet sent to or received from a graph stream. Only the fields of its type are used.
+//
+// Landmark and classification lists are stored back to back in `landmarks` and
+// `classifications`, with the length of each list in `list_sizes`. Input packets with a single
+// list (or rect) are sent as one message rather than a std::vector. Matrices are column-major
+// in `floats`, `rows` by `cols` each, with the number of matrices in `num_lists`.
+//
+// The arrays of a received packet are owned by the graph and stay valid until the next packet is
+// read from the same output, or until the graph is destroyed.
+struct Packet {
+  PacketType type;
+  int64_t timestamp_us;
+  Image image;
+  const Landmark* landmarks;
+  const Classification* classifications;
+  const int* list_sizes;
+  int num_lists;
+  const Detection* detections;
+  int num_detections;
+  const Rect* rects;
+  int num_rects;
+  const float* floats;
+  int num_floats;
+  int rows;
+  int cols;
+};
+
+// A calculator option set when a graph is created, `value` in protobuf text format.
+//
+// `node` is matched against the calculator types and the node names of the expanded graph, or the
+// end of them, so options of nodes inside subgraphs can be set, e.g.
+// "handlandmarkcpu__ThresholdingCalculator". The option is set on every matching node, creating
+// the graph fails if there is none. `field` may be a dotted path into the node's options.
+struct CalculatorOption {
+  const char* node;
+  const char* field;
+  const char* value;
+};
+
+// A model file served from memory, `path` relative to the resource root.
+struct Resource {
+  const char* path;
+  const uint8_t* data;
+  size_t size;
+};
+
+// Where a graph reads its model files from. `files` take precedence, other files are read from
+// `root_dir`, or from the directory set with set_resource_root_dir if it is null. The library
+// copies the files, they only have to stay valid during the call.
+struct Resources {
+  const char* root_dir;
+  const Resource* files;
+  uint32_t num_files;
+};
+
+// Runs a graph with the image input stream "input_video" and returns the features of each output
+// per frame.
+class Detector {
+ public:
+  // Creates and starts a graph, returns null on failure.
+  static Detector* Create(const char* graph_config, const Output* outputs, uint8_t num_outputs);
+  // Like Create, also setting the given calculator options and reading the model files from
+  // `resources`, which may be null.
+  static Detector* CreateWithOptions(const char* graph_config, const Output* outputs, uint8_t num_outputs, const CalculatorOption* options, uint8_t num_options,
+                                     const Resources* resources);
+  // Stops the graph and frees the detector.
+  static void Destroy(Detector* detector);
+
+  // Processes a packed RGB frame and waits for the graph to finish it.
+  //
+  // Writes the number of features found to `num_features[i]` for every output and returns their
+  // landmarks back to back, in output order. Outputs without landmarks report 1 if the frame
+  // produced a packet on them and 0 otherwise, their contents are read with GetPacket. The
+  // landmarks are owned by the detector and stay valid until the next call to Process.
+  Landmark* Process(uint8_t* data, int width, int height, int64_t timestamp_us, uint8_t* num_features);
+
+  // Reads the packet the last processed frame produced on an output without landmarks. Returns
+  // false if there was none.
+  bool GetPacket(uint8_t output, Packet* packet);
+
+ private:
+  struct Impl;
+  explicit Detector(Impl* impl);
+  ~Detector();
+  Impl* impl_;
+};
+
+// Runs a graph with the image input stream "input_video" and returns the frame rendered to the
+// output stream `output_node`.
+class Effect {
+ public:
+  // Creates and starts a graph, returns null on failure.
+  static Effect* Create(const char* graph_config, const char* output_node);
+  // Stops the graph and frees the effect.
+  static void Destroy(Effect* effect);
+
+  // Processes a packed RGB frame and waits for the graph to finish it. Returns the rendered
+  // frame, with a null `data` if the graph produced none. The pixel buffer is owned by the effect
+  // and stays valid until the next call to Process, see Image.
+  Image Process(uint8_t* data, int width, int height, int64_t timestamp_us);
+
+ private:
+  struct Impl;
+  explicit Effect(Impl* impl);
+  ~Effect();
+  Impl* impl_;
+};
+
+// Runs a graph with arbitrary typed input and output streams.
+class Graph {
+ public:
+  // Creates and starts a graph, returns null on failure.
+  static Graph* Create(const char* graph_config, const Stream* inputs, uint8_t num_inputs, const Stream* outputs, uint8_t num_outputs);
+  // Closes the inputs, waits for the graph to finish and frees it.
+  static void Destroy(Graph* graph);
+
+  // Sends a copy of `packet` to an input stream.
+  void AddPacket(uint8_t input, const Packet* packet);
+
+  // Reads the next packet of an output stream, blocking until there is one if `wait` is set.
+  // Returns false if there is none, or if the stream was closed.
+  bool Poll(uint8_t output, bool wait, Packet* packet);
+
+  // Blocks until the graph processed all packets sent so far.
+  void WaitUntilIdle();
+
+ private:
+  struct Impl;
+  explicit Graph(Impl* impl);
+  ~Graph();
+  Impl* impl_;
+};
+
+}  // namespace mediagraph
+
+#endif  // MEDIAGRAPH_H
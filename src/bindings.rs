@@ -2,4 +2,18 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+mod raw {
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
+pub use raw::*;
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+// Unit tests run against the mock, the explicit imports shadow the library calls of the glob.
+#[cfg(test)]
+pub use mock::{
+    mediagraph_Detector_Create, mediagraph_Detector_CreateWithOptions, mediagraph_Detector_Destroy, mediagraph_Detector_GetPacket, mediagraph_Detector_Process, mediagraph_Effect_Create, mediagraph_Effect_Destroy, mediagraph_Effect_Process,
+    mediagraph_Graph_AddPacket, mediagraph_Graph_Create, mediagraph_Graph_Destroy, mediagraph_Graph_Poll, mediagraph_Graph_WaitUntilIdle, mediagraph_last_status, mediagraph_set_resource_root_dir,
+};
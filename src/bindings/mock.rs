@@ -0,0 +1,236 @@
+//! A stand-in for the C++ library in unit tests.
+//!
+//! The graphs are plain heap allocations that never run anything, so the wrappers can be tested
+//! without MediaPipe, and under Miri or a leak checker. Detectors find no features, effects
+//! render the input frame unchanged and graphs echo the packets of input `i` on output `i`.
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    ptr, slice,
+};
+
+use super::raw::*;
+
+/// Padding added to every row of the frames rendered by an effect, so callers have to honour
+/// `width_step`.
+const ROW_PADDING: usize = 5;
+
+thread_local! {
+    static LIVE_GRAPHS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Number of mock graphs created and not destroyed yet on this thread.
+pub(crate) fn live_graphs() -> usize {
+    LIVE_GRAPHS.with(|n| n.get())
+}
+
+fn create<T>(graph: T) -> *mut T {
+    LIVE_GRAPHS.with(|n| n.set(n.get() + 1));
+    Box::into_raw(Box::new(graph))
+}
+
+unsafe fn destroy<T>(graph: *mut T) {
+    assert!(!graph.is_null(), "destroyed a null graph");
+    LIVE_GRAPHS.with(|n| n.set(n.get() - 1));
+    drop(Box::from_raw(graph));
+}
+
+pub unsafe fn mediagraph_last_status() -> mediagraph_Status {
+    mediagraph_Status { code: 0, message: ptr::null() }
+}
+
+pub unsafe fn mediagraph_set_resource_root_dir(_path: *const c_char) {}
+
+struct MockDetector {
+    num_outputs: usize,
+    landmarks: Vec<mediagraph_Landmark>,
+}
+
+pub unsafe fn mediagraph_Detector_Create(_graph_config: *const c_char, _outputs: *const mediagraph_Output, num_outputs: u8) -> *mut mediagraph_Detector {
+    create(MockDetector {
+        num_outputs: num_outputs as usize,
+        landmarks: vec![],
+    }) as *mut mediagraph_Detector
+}
+
+pub unsafe fn mediagraph_Detector_CreateWithOptions(
+    graph_config: *const c_char,
+    outputs: *const mediagraph_Output,
+    num_outputs: u8,
+    _options: *const mediagraph_CalculatorOption,
+    _num_options: u8,
+    _resources: *const mediagraph_Resources,
+) -> *mut mediagraph_Detector {
+    mediagraph_Detector_Create(graph_config, outputs, num_outputs)
+}
+
+pub unsafe fn mediagraph_Detector_Destroy(detector: *mut mediagraph_Detector) {
+    destroy(detector as *mut MockDetector)
+}
+
+pub unsafe fn mediagraph_Detector_Process(this: *mut mediagraph_Detector, _data: *mut u8, _width: c_int, _height: c_int, _timestamp_us: i64, num_features: *mut u8) -> *mut mediagraph_Landmark {
+    let detector = &mut *(this as *mut MockDetector);
+    ptr::write_bytes(num_features, 0, detector.num_outputs);
+    detector.landmarks.as_mut_ptr()
+}
+
+pub unsafe fn mediagraph_Detector_GetPacket(_this: *mut mediagraph_Detector, _output: u8, _packet: *mut mediagraph_Packet) -> bool {
+    false
+}
+
+struct MockEffect {
+    frame: Vec<u8>,
+}
+
+pub unsafe fn mediagraph_Effect_Create(_graph_config: *const c_char, _output_node: *const c_char) -> *mut mediagraph_Effect {
+    create(MockEffect { frame: vec![] }) as *mut mediagraph_Effect
+}
+
+pub unsafe fn mediagraph_Effect_Destroy(effect: *mut mediagraph_Effect) {
+    destroy(effect as *mut MockEffect)
+}
+
+pub unsafe fn mediagraph_Effect_Process(this: *mut mediagraph_Effect, data: *mut u8, width: c_int, height: c_int, _timestamp_us: i64) -> mediagraph_Image {
+    let effect = &mut *(this as *mut MockEffect);
+    let row_len = width as usize * 3;
+    let width_step = row_len + ROW_PADDING;
+
+    effect.frame.clear();
+    for row in std::slice::from_raw_parts(data, row_len * height as usize).chunks(row_len) {
+        effect.frame.extend_from_slice(row);
+        effect.frame.extend_from_slice(&[0; ROW_PADDING]);
+    }
+
+    mediagraph_Image {
+        data: effect.frame.as_mut_ptr(),
+        width,
+        height,
+        width_step: width_step as c_int,
+        channels: 3,
+        is_float: false,
+    }
+}
+
+/// A deep copy of a packet, the raw packet points into the buffers next to it.
+struct MockPacket {
+    raw: mediagraph_Packet,
+    _pixels: Vec<u8>,
+    _list_sizes: Vec<i32>,
+    _landmarks: Vec<mediagraph_Landmark>,
+    _strings: Vec<CString>,
+    _classifications: Vec<mediagraph_Classification>,
+    _keypoints: Vec<Vec<mediagraph_Keypoint>>,
+    _detections: Vec<mediagraph_Detection>,
+    _rects: Vec<mediagraph_Rect>,
+    _floats: Vec<f32>,
+}
+
+unsafe fn copy_slice<T: Copy>(ptr: *const T, len: c_int) -> Vec<T> {
+    if ptr.is_null() || len <= 0 {
+        vec![]
+    } else {
+        slice::from_raw_parts(ptr, len as usize).to_vec()
+    }
+}
+
+/// Copies a string into `strings` and returns the pointer to the copy, null stays null.
+unsafe fn copy_string(strings: &mut Vec<CString>, s: *const c_char) -> *const c_char {
+    if s.is_null() {
+        return ptr::null();
+    }
+    let s = CStr::from_ptr(s).to_owned();
+    let ptr = s.as_ptr();
+    strings.push(s);
+    ptr
+}
+
+impl MockPacket {
+    unsafe fn copy(packet: &mediagraph_Packet) -> Self {
+        let mut raw = *packet;
+        let mut pixels = vec![];
+        if !packet.image.data.is_null() {
+            pixels = slice::from_raw_parts(packet.image.data, (packet.image.width_step * packet.image.height) as usize).to_vec();
+            raw.image.data = pixels.as_mut_ptr();
+        }
+        let list_sizes = copy_slice(packet.list_sizes, packet.num_lists);
+        let total = list_sizes.iter().map(|&s| s.max(0)).sum::<i32>();
+        let landmarks = copy_slice(packet.landmarks, total);
+        let mut strings = vec![];
+        let mut classifications = copy_slice(packet.classifications, total);
+        for c in &mut classifications {
+            c.label = copy_string(&mut strings, c.label);
+            c.display_name = copy_string(&mut strings, c.display_name);
+        }
+        let mut detections = copy_slice(packet.detections, packet.num_detections);
+        let mut keypoints = vec![];
+        for d in &mut detections {
+            d.label = copy_string(&mut strings, d.label);
+            let copy = copy_slice(d.keypoints, d.num_keypoints);
+            d.keypoints = copy.as_ptr();
+            keypoints.push(copy);
+        }
+        let rects = copy_slice(packet.rects, packet.num_rects);
+        let floats = copy_slice(packet.floats, packet.num_floats);
+
+        raw.list_sizes = list_sizes.as_ptr();
+        raw.landmarks = landmarks.as_ptr();
+        raw.classifications = classifications.as_ptr();
+        raw.detections = detections.as_ptr();
+        raw.rects = rects.as_ptr();
+        raw.floats = floats.as_ptr();
+        Self {
+            raw,
+            _pixels: pixels,
+            _list_sizes: list_sizes,
+            _landmarks: landmarks,
+            _strings: strings,
+            _classifications: classifications,
+            _keypoints: keypoints,
+            _detections: detections,
+            _rects: rects,
+            _floats: floats,
+        }
+    }
+}
+
+struct MockGraph {
+    queues: Vec<VecDeque<MockPacket>>,
+    // The last packet polled from each output, which the caller may still be reading.
+    polled: Vec<Option<MockPacket>>,
+}
+
+pub unsafe fn mediagraph_Graph_Create(_graph_config: *const c_char, _inputs: *const mediagraph_Stream, _num_inputs: u8, _outputs: *const mediagraph_Stream, num_outputs: u8) -> *mut mediagraph_Graph {
+    create(MockGraph {
+        queues: (0..num_outputs).map(|_| VecDeque::new()).collect(),
+        polled: (0..num_outputs).map(|_| None).collect(),
+    }) as *mut mediagraph_Graph
+}
+
+pub unsafe fn mediagraph_Graph_Destroy(graph: *mut mediagraph_Graph) {
+    destroy(graph as *mut MockGraph)
+}
+
+pub unsafe fn mediagraph_Graph_AddPacket(this: *mut mediagraph_Graph, input: u8, packet: *const mediagraph_Packet) {
+    let graph = &mut *(this as *mut MockGraph);
+    // Inputs without a matching output are dropped, like a graph that has nothing to report.
+    if let Some(queue) = graph.queues.get_mut(input as usize) {
+        queue.push_back(MockPacket::copy(&*packet));
+    }
+}
+
+pub unsafe fn mediagraph_Graph_Poll(this: *mut mediagraph_Graph, output: u8, _wait: bool, packet: *mut mediagraph_Packet) -> bool {
+    let graph = &mut *(this as *mut MockGraph);
+    let output = output as usize;
+    graph.polled[output] = graph.queues[output].pop_front();
+    match &graph.polled[output] {
+        Some(p) => {
+            *packet = p.raw;
+            true
+        }
+        None => false,
+    }
+}
+
+pub unsafe fn mediagraph_Graph_WaitUntilIdle(_this: *mut mediagraph_Graph) {}
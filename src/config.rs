@@ -0,0 +1,262 @@
+//! Rewriting the options of the embedded graph configs.
+use std::{convert::TryFrom, fmt};
+
+use super::Error;
+
+/// The value of a side packet generated by a `ConstantSidePacketCalculator`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SidePacket {
+    Int(i32),
+    Bool(bool),
+}
+
+impl SidePacket {
+    /// A count option like the maximum number of hands, which has to be at least one.
+    pub(crate) fn count(option: &str, value: usize) -> Result<Self, Error> {
+        match i32::try_from(value) {
+            Ok(v) if v > 0 => Ok(SidePacket::Int(v)),
+            _ => Err(Error::InvalidGraphConfig(format!("{} must be between 1 and {}, got {}", option, i32::MAX, value))),
+        }
+    }
+}
+
+impl fmt::Display for SidePacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidePacket::Int(v) => write!(f, "packet {{ int_value: {} }}", v),
+            SidePacket::Bool(v) => write!(f, "packet {{ bool_value: {} }}", v),
+        }
+    }
+}
+
+/// The value of a calculator option field.
+#[derive(Debug, Clone)]
+pub(crate) enum FieldValue {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Str(String),
+}
+
+impl FieldValue {
+    /// A confidence threshold, which has to be between 0 and 1.
+    pub(crate) fn confidence(option: &str, value: f32) -> Result<Self, Error> {
+        if (0.0..=1.0).contains(&value) {
+            Ok(FieldValue::Float(value))
+        } else {
+            Err(Error::InvalidGraphConfig(format!("{} must be between 0 and 1, got {}", option, value)))
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Int(v) => write!(f, "{}", v),
+            FieldValue::Float(v) => write!(f, "{:?}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::Str(v) => write!(f, "\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\"")),
+        }
+    }
+}
+
+/// An option of a calculator inside a subgraph, out of reach of the text config. The library
+/// sets it once the subgraphs are expanded.
+#[derive(Debug, Clone)]
+pub(crate) struct CalculatorOption {
+    /// End of the expanded node name, e.g. `posedetectioncpu__TensorsToDetectionsCalculator`.
+    pub(crate) node: String,
+    pub(crate) field: String,
+    pub(crate) value: FieldValue,
+}
+
+/// A graph config in text format, edited before the graph is created.
+///
+/// The edits are line based and rely on the layout of the configs in `src/graphs`, one field per
+/// line and nodes closed by a `}` in the first column.
+#[derive(Debug, Clone)]
+pub(crate) struct GraphConfig {
+    lines: Vec<String>,
+    calculator_options: Vec<CalculatorOption>,
+}
+
+impl GraphConfig {
+    pub(crate) fn new(config: &str) -> Self {
+        Self {
+            lines: config.lines().map(String::from).collect(),
+            calculator_options: vec![],
+        }
+    }
+
+    /// Sets the value of the constant side packet `name`.
+    pub(crate) fn set_side_packet(&mut self, name: &str, value: SidePacket) -> Result<&mut Self, Error> {
+        let not_found = || Error::InvalidGraphConfig(format!("graph has no constant side packet {}", name));
+
+        // Side packets are declared as `PACKET:name` or `PACKET:<index>:name`.
+        let (line, index) = self
+            .lines
+            .iter()
+            .enumerate()
+            .find_map(|(i, line)| {
+                let tag = line.trim().strip_prefix("output_side_packet: \"PACKET:")?.strip_suffix('"')?;
+                match tag.split_once(':') {
+                    None if tag == name => Some((i, 0)),
+                    Some((index, tag)) if tag == name => Some((i, index.parse::<usize>().ok()?)),
+                    _ => None,
+                }
+            })
+            .ok_or_else(not_found)?;
+
+        let packet = self.lines[line..]
+            .iter()
+            .take_while(|l| !l.starts_with('}'))
+            .enumerate()
+            .filter(|(_, l)| l.trim_start().starts_with("packet {"))
+            .nth(index)
+            .map(|(i, _)| line + i)
+            .ok_or_else(not_found)?;
+
+        let indent = self.lines[packet].len() - self.lines[packet].trim_start().len();
+        self.lines[packet] = format!("{}{}", &self.lines[packet][..indent], value);
+        Ok(self)
+    }
+
+    /// Sets the calculator option `field` to `values`, one line per value for repeated fields and
+    /// none to leave it unset. The field has to appear exactly once in the config.
+    pub(crate) fn set_field(&mut self, field: &str, values: &[FieldValue]) -> Result<&mut Self, Error> {
+        let prefix = format!("{}:", field);
+        let mut lines = self.lines.iter().enumerate().filter(|(_, l)| l.trim_start().starts_with(&prefix)).map(|(i, _)| i);
+        let line = match (lines.next(), lines.next()) {
+            (Some(line), None) => line,
+            _ => return Err(Error::InvalidGraphConfig(format!("graph has no unique option {}", field))),
+        };
+
+        let indent = self.lines[line].len() - self.lines[line].trim_start().len();
+        let indent = self.lines[line][..indent].to_string();
+        self.lines.splice(line..=line, values.iter().map(|v| format!("{}{}: {}", indent, field, v)));
+        Ok(self)
+    }
+
+    /// Sets the option `field` of the subgraph calculators whose type is `node` or whose expanded
+    /// name ends with `node`. Creating the graph fails with [Error::InvalidGraphConfig] if no
+    /// calculator matches.
+    pub(crate) fn set_calculator_option(&mut self, node: &str, field: &str, value: FieldValue) -> &mut Self {
+        self.calculator_options.push(CalculatorOption {
+            node: node.into(),
+            field: field.into(),
+            value,
+        });
+        self
+    }
+
+    /// The edited config.
+    pub(crate) fn to_text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// The options to set once the subgraphs are expanded.
+    pub(crate) fn calculator_options(&self) -> &[CalculatorOption] {
+        &self.calculator_options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDE_PACKETS: &str = r#"node {
+  calculator: "ConstantSidePacketCalculator"
+  output_side_packet: "PACKET:0:num_faces"
+  output_side_packet: "PACKET:1:with_attention"
+  node_options: {
+    [type.googleapis.com/mediapipe.ConstantSidePacketCalculatorOptions]: {
+      packet { int_value: 1 }
+      packet { bool_value: false }
+    }
+  }
+}
+
+node {
+  calculator: "ConstantSidePacketCalculator"
+  output_side_packet: "PACKET:use_prev_landmarks"
+  node_options: {
+    [type.googleapis.com/mediapipe.ConstantSidePacketCalculatorOptions]: {
+      packet { bool_value: true }
+    }
+  }
+}"#;
+
+    const OPTIONS: &str = r#"node {
+  calculator: "LabelsCalculator"
+  options {
+    [mediapipe.LabelsCalculatorOptions.ext] {
+      min_score: 0.5
+      label: "cat"
+      max_results: 3
+    }
+  }
+}"#;
+
+    #[test]
+    fn side_packets_are_found_by_index() {
+        let mut config = GraphConfig::new(SIDE_PACKETS);
+        config.set_side_packet("with_attention", SidePacket::Bool(true)).unwrap();
+        config.set_side_packet("num_faces", SidePacket::Int(4)).unwrap();
+        config.set_side_packet("use_prev_landmarks", SidePacket::Bool(false)).unwrap();
+
+        let text = config.to_text();
+        assert!(text.contains("      packet { int_value: 4 }\n      packet { bool_value: true }\n"));
+        assert!(text.contains("      packet { bool_value: false }\n    }\n  }\n}"));
+        assert_eq!(text.lines().count(), SIDE_PACKETS.lines().count());
+    }
+
+    #[test]
+    fn missing_side_packets_are_rejected() {
+        let mut config = GraphConfig::new(SIDE_PACKETS);
+        assert!(matches!(config.set_side_packet("num_hands", SidePacket::Int(1)), Err(Error::InvalidGraphConfig(_))));
+        // A prefix of a declared name doesn't match.
+        assert!(matches!(config.set_side_packet("num", SidePacket::Int(1)), Err(Error::InvalidGraphConfig(_))));
+        // The index points past the packets of the node.
+        let mut config = GraphConfig::new(&SIDE_PACKETS.replace("PACKET:1:", "PACKET:2:"));
+        assert!(matches!(config.set_side_packet("with_attention", SidePacket::Bool(true)), Err(Error::InvalidGraphConfig(_))));
+    }
+
+    #[test]
+    fn fields_are_replaced_with_one_line_per_value() {
+        let mut config = GraphConfig::new(OPTIONS);
+        config.set_field("min_score", &[FieldValue::Float(0.25)]).unwrap();
+        config.set_field("label", &[FieldValue::Str("dog".into()), FieldValue::Str("bird".into())]).unwrap();
+
+        let text = config.to_text();
+        assert!(text.contains("      min_score: 0.25\n      label: \"dog\"\n      label: \"bird\"\n      max_results: 3\n"));
+    }
+
+    #[test]
+    fn empty_values_remove_the_field() {
+        let mut config = GraphConfig::new(OPTIONS);
+        config.set_field("label", &[]).unwrap();
+
+        let text = config.to_text();
+        assert!(!text.contains("label:"));
+        assert!(text.contains("      min_score: 0.5\n      max_results: 3\n"));
+        // The field is gone, so it can't be set again.
+        assert!(matches!(config.set_field("label", &[FieldValue::Str("cat".into())]), Err(Error::InvalidGraphConfig(_))));
+    }
+
+    #[test]
+    fn missing_and_duplicate_fields_are_rejected() {
+        let mut config = GraphConfig::new(OPTIONS);
+        assert!(matches!(config.set_field("score_threshold", &[FieldValue::Float(0.5)]), Err(Error::InvalidGraphConfig(_))));
+
+        let mut config = GraphConfig::new(&OPTIONS.replace("      label: \"cat\"\n", "      label: \"cat\"\n      label: \"dog\"\n"));
+        assert!(matches!(config.set_field("label", &[]), Err(Error::InvalidGraphConfig(_))));
+        assert!(config.to_text().contains("label: \"cat\"\n      label: \"dog\""));
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(FieldValue::Str(r#"C:\models\"a".tflite"#.into()).to_string(), r#""C:\\models\\\"a\".tflite""#);
+        assert_eq!(FieldValue::Float(1.0).to_string(), "1.0");
+        assert_eq!(SidePacket::Int(2).to_string(), "packet { int_value: 2 }");
+    }
+}
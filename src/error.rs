@@ -0,0 +1,77 @@
+//! Error type shared by all detectors and effects.
+use std::{ffi::CStr, fmt};
+
+use super::bindings::*;
+
+/// absl status code reported by the C++ library when a file could not be found.
+const STATUS_NOT_FOUND: i32 = 5;
+
+/// Errors returned by the graph wrappers.
+#[derive(Debug)]
+pub enum Error {
+    /// The graph config could not be parsed, validated or started.
+    InvalidGraphConfig(String),
+    /// A model or other resource file required by the graph is missing.
+    MissingResource(String),
+    /// The input frame can not be processed (empty, or wrong type or size for its pixel format).
+    InvalidInput(String),
+    /// A packet timestamp is not after the previous one.
+    InvalidTimestamp(String),
+    /// The graph failed while processing a frame.
+    Runtime(String),
+    /// An OpenCV call failed.
+    OpenCv(opencv::Error),
+}
+
+impl Error {
+    /// Reads the status of the last library call made on this thread.
+    ///
+    /// Returns `None` if that call succeeded. Missing files are reported as [Error::MissingResource],
+    /// everything else is wrapped with `kind`.
+    pub(crate) fn from_last_status(kind: fn(String) -> Error) -> Option<Error> {
+        let status = unsafe { mediagraph_last_status() };
+        if status.code == 0 {
+            return None;
+        }
+
+        let message = if status.message.is_null() {
+            format!("mediagraph status code {}", status.code)
+        } else {
+            unsafe { CStr::from_ptr(status.message) }.to_string_lossy().into_owned()
+        };
+
+        if status.code == STATUS_NOT_FOUND {
+            Some(Error::MissingResource(message))
+        } else {
+            Some(kind(message))
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidGraphConfig(msg) => write!(f, "invalid graph config: {}", msg),
+            Error::MissingResource(msg) => write!(f, "missing resource: {}", msg),
+            Error::InvalidInput(msg) => write!(f, "invalid input: {}", msg),
+            Error::InvalidTimestamp(msg) => write!(f, "invalid timestamp: {}", msg),
+            Error::Runtime(msg) => write!(f, "graph runtime error: {}", msg),
+            Error::OpenCv(e) => write!(f, "opencv error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::OpenCv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<opencv::Error> for Error {
+    fn from(e: opencv::Error) -> Self {
+        Error::OpenCv(e)
+    }
+}
@@ -0,0 +1,149 @@
+//! Lightweight face detection utilities.
+use super::*;
+
+pub const NUM_FACE_KEYPOINTS: usize = 6;
+
+/// Face keypoint indices.
+pub enum FaceKeypoint {
+    RIGHT_EYE = 0,
+    LEFT_EYE = 1,
+    NOSE_TIP = 2,
+    MOUTH_CENTER = 3,
+    RIGHT_EAR_TRAGION = 4,
+    LEFT_EAR_TRAGION = 5,
+}
+
+/// The face detection model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FaceDetectionModel {
+    /// For faces within about 2 meters of the camera, e.g. selfies.
+    #[default]
+    ShortRange,
+    /// For faces within about 5 meters of the camera.
+    FullRange,
+}
+
+/// A detected face.
+#[derive(Clone, Debug)]
+pub struct FaceDetection {
+    /// Bounding box in normalized image coordinates.
+    pub bbox: BoundingBox,
+    /// Detection confidence, between 0 and 1.
+    pub score: f32,
+    /// Keypoint names are in [FaceKeypoint].
+    pub keypoints: [Keypoint; NUM_FACE_KEYPOINTS],
+}
+
+impl FaceDetection {
+    fn from_detection(detection: Detection) -> Result<Self, Error> {
+        let keypoints = <[Keypoint; NUM_FACE_KEYPOINTS]>::try_from(detection.keypoints.as_slice()).map_err(|_| Error::Runtime(format!("expected {} face keypoints, got {}", NUM_FACE_KEYPOINTS, detection.keypoints.len())))?;
+
+        Ok(Self {
+            bbox: detection.bbox,
+            score: detection.score,
+            keypoints,
+        })
+    }
+}
+
+/// Configures a [FaceDetector].
+#[derive(Debug, Clone, Default)]
+pub struct FaceDetectorBuilder {
+    model: FaceDetectionModel,
+    resources: Resources,
+}
+
+impl FaceDetectorBuilder {
+    /// Sets the detection model, defaults to [FaceDetectionModel::ShortRange].
+    pub fn model(mut self, model: FaceDetectionModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Sets where the model files are read from, defaults to the directory set with
+    /// [set_resource_root_dir].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [FaceDetectorBuilder::try_build].
+    pub fn build(self) -> FaceDetector {
+        self.try_build().expect("Failed to create face detector")
+    }
+
+    pub fn try_build(self) -> Result<FaceDetector, Error> {
+        let (graph_config, model) = match self.model {
+            FaceDetectionModel::ShortRange => (include_str!("graphs/face_detection_short_range_cpu.pbtxt"), "mediapipe/modules/face_detection/face_detection_short_range.tflite"),
+            FaceDetectionModel::FullRange => (include_str!("graphs/face_detection_full_range_cpu.pbtxt"), "mediapipe/modules/face_detection/face_detection_full_range_sparse.tflite"),
+        };
+        self.resources.check(&[model])?;
+
+        let graph = Detector::try_from_config(
+            &GraphConfig::new(graph_config),
+            &self.resources,
+            vec![Output {
+                type_: FeatureType::Detections,
+                name: "face_detections".into(),
+            }],
+        )?;
+
+        Ok(FaceDetector { graph, buffer: LandmarkBuffer::new() })
+    }
+}
+
+/// Detects faces without computing their mesh.
+pub struct FaceDetector {
+    graph: Detector,
+    buffer: LandmarkBuffer,
+}
+
+impl FaceDetector {
+    pub fn new() -> Self {
+        Self::try_new().expect("Failed to create face detector")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a face detector.
+    pub fn builder() -> FaceDetectorBuilder {
+        FaceDetectorBuilder::default()
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
+    }
+
+    /// Processes the input frame, returns the detected faces.
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<FaceDetection> {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns the detected faces.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<FaceDetection>, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the detected faces.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<FaceDetection> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the detected faces.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<FaceDetection>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+
+        let detections = self.graph.packet(0)?.and_then(|p| p.data.into_detections()).unwrap_or_default();
+        detections.into_iter().map(FaceDetection::from_detection).collect()
+    }
+}
+
+impl Default for FaceDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,35 +1,320 @@
 //! Face detection utilities.
 use super::*;
 
+pub const NUM_BLENDSHAPES: usize = 52;
+
+/// Blendshape indices, in the order of MediaPipe's blendshape model. The names follow ARKit's
+/// blend shape locations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blendshape {
+    NEUTRAL = 0,
+    BROW_DOWN_LEFT = 1,
+    BROW_DOWN_RIGHT = 2,
+    BROW_INNER_UP = 3,
+    BROW_OUTER_UP_LEFT = 4,
+    BROW_OUTER_UP_RIGHT = 5,
+    CHEEK_PUFF = 6,
+    CHEEK_SQUINT_LEFT = 7,
+    CHEEK_SQUINT_RIGHT = 8,
+    EYE_BLINK_LEFT = 9,
+    EYE_BLINK_RIGHT = 10,
+    EYE_LOOK_DOWN_LEFT = 11,
+    EYE_LOOK_DOWN_RIGHT = 12,
+    EYE_LOOK_IN_LEFT = 13,
+    EYE_LOOK_IN_RIGHT = 14,
+    EYE_LOOK_OUT_LEFT = 15,
+    EYE_LOOK_OUT_RIGHT = 16,
+    EYE_LOOK_UP_LEFT = 17,
+    EYE_LOOK_UP_RIGHT = 18,
+    EYE_SQUINT_LEFT = 19,
+    EYE_SQUINT_RIGHT = 20,
+    EYE_WIDE_LEFT = 21,
+    EYE_WIDE_RIGHT = 22,
+    JAW_FORWARD = 23,
+    JAW_LEFT = 24,
+    JAW_OPEN = 25,
+    JAW_RIGHT = 26,
+    MOUTH_CLOSE = 27,
+    MOUTH_DIMPLE_LEFT = 28,
+    MOUTH_DIMPLE_RIGHT = 29,
+    MOUTH_FROWN_LEFT = 30,
+    MOUTH_FROWN_RIGHT = 31,
+    MOUTH_FUNNEL = 32,
+    MOUTH_LEFT = 33,
+    MOUTH_LOWER_DOWN_LEFT = 34,
+    MOUTH_LOWER_DOWN_RIGHT = 35,
+    MOUTH_PRESS_LEFT = 36,
+    MOUTH_PRESS_RIGHT = 37,
+    MOUTH_PUCKER = 38,
+    MOUTH_RIGHT = 39,
+    MOUTH_ROLL_LOWER = 40,
+    MOUTH_ROLL_UPPER = 41,
+    MOUTH_SHRUG_LOWER = 42,
+    MOUTH_SHRUG_UPPER = 43,
+    MOUTH_SMILE_LEFT = 44,
+    MOUTH_SMILE_RIGHT = 45,
+    MOUTH_STRETCH_LEFT = 46,
+    MOUTH_STRETCH_RIGHT = 47,
+    MOUTH_UPPER_UP_LEFT = 48,
+    MOUTH_UPPER_UP_RIGHT = 49,
+    NOSE_SNEER_LEFT = 50,
+    NOSE_SNEER_RIGHT = 51,
+}
+
+/// The expression and head pose of a face.
+#[derive(Clone, Debug)]
+pub struct FaceGeometry {
+    /// Blendshape scores between 0 and 1, names are in [Blendshape].
+    pub blendshapes: [f32; NUM_BLENDSHAPES],
+    /// 4x4 transformation from the canonical face model to the face in camera space, in
+    /// centimeters. The camera looks along the negative z axis.
+    pub transformation_matrix: Matrix,
+}
+
+impl FaceGeometry {
+    /// The score of `blendshape`.
+    pub fn blendshape(&self, blendshape: Blendshape) -> f32 {
+        self.blendshapes[blendshape as usize]
+    }
+}
+
+pub(crate) const FACE_LANDMARK_MODEL: &str = "mediapipe/modules/face_landmark/face_landmark.tflite";
+
+/// The calculators applying the confidence thresholds of the face subgraphs. The detection
+/// threshold is looked up by calculator type, the face detection is the only detector in the graph.
+const FACE_DETECTION_NODE: &str = "TensorsToDetectionsCalculator";
+const FACE_TRACKING_NODE: &str = "facelandmarkcpu__ThresholdingCalculator";
+
+/// Configures a [FaceMeshDetector].
+#[derive(Debug, Clone)]
+pub struct FaceMeshDetectorBuilder {
+    max_faces: usize,
+    refine_landmarks: bool,
+    face_geometry: bool,
+    running_mode: RunningMode,
+    min_detection_confidence: f32,
+    min_tracking_confidence: f32,
+    resources: Resources,
+}
+
+impl Default for FaceMeshDetectorBuilder {
+    fn default() -> Self {
+        Self {
+            max_faces: 2,
+            refine_landmarks: true,
+            face_geometry: false,
+            running_mode: RunningMode::default(),
+            min_detection_confidence: 0.5,
+            min_tracking_confidence: 0.5,
+            resources: Resources::default(),
+        }
+    }
+}
+
+impl FaceMeshDetectorBuilder {
+    /// Sets the maximum number of faces to detect and track, defaults to 2.
+    pub fn max_faces(mut self, max_faces: usize) -> Self {
+        self.max_faces = max_faces;
+        self
+    }
+
+    /// Enables the attention model refining the landmarks around the eyes and lips, defaults to
+    /// `true`.
+    ///
+    /// Without it the mesh has 468 landmarks, the 10 iris landmarks of [FaceMesh] are left at zero.
+    pub fn refine_landmarks(mut self, refine_landmarks: bool) -> Self {
+        self.refine_landmarks = refine_landmarks;
+        self
+    }
+
+    /// Estimates the blendshapes and head pose of each face, read them with
+    /// [FaceMeshDetector::face_geometry], defaults to `false`.
+    ///
+    /// The blendshape model needs the refined landmarks, see
+    /// [FaceMeshDetectorBuilder::refine_landmarks].
+    pub fn face_geometry(mut self, face_geometry: bool) -> Self {
+        self.face_geometry = face_geometry;
+        self
+    }
+
+    /// Sets how the frames passed to the detector relate to each other, defaults to
+    /// [RunningMode::LiveStream].
+    pub fn running_mode(mut self, running_mode: RunningMode) -> Self {
+        self.running_mode = running_mode;
+        self
+    }
+
+    /// Sets the minimum score of a face detection for it to be tracked, defaults to 0.5.
+    pub fn min_detection_confidence(mut self, min_detection_confidence: f32) -> Self {
+        self.min_detection_confidence = min_detection_confidence;
+        self
+    }
+
+    /// Sets the minimum landmark score for the face to be tracked into the next frame, defaults to
+    /// 0.5. Below it the detection model runs again.
+    pub fn min_tracking_confidence(mut self, min_tracking_confidence: f32) -> Self {
+        self.min_tracking_confidence = min_tracking_confidence;
+        self
+    }
+
+    /// Sets where the model files are read from, defaults to the directory set with
+    /// [set_resource_root_dir].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [FaceMeshDetectorBuilder::try_build].
+    pub fn build(self) -> FaceMeshDetector {
+        self.try_build().expect("Failed to create face mesh detector")
+    }
+
+    pub fn try_build(self) -> Result<FaceMeshDetector, Error> {
+        if self.face_geometry && !self.refine_landmarks {
+            return Err(Error::InvalidGraphConfig("face_geometry needs refine_landmarks".into()));
+        }
+
+        let mut models = vec![
+            "mediapipe/modules/face_detection/face_detection_short_range.tflite",
+            if self.refine_landmarks {
+                "mediapipe/modules/face_landmark/face_landmark_with_attention.tflite"
+            } else {
+                FACE_LANDMARK_MODEL
+            },
+        ];
+        if self.face_geometry {
+            models.push("mediapipe/modules/face_landmark/face_blendshapes.tflite");
+            models.push("mediapipe/modules/face_geometry/data/geometry_pipeline_metadata_landmarks.binarypb");
+        }
+        self.resources.check(&models)?;
+
+        let mut config = GraphConfig::new(if self.face_geometry {
+            include_str!("graphs/face_geometry_desktop_live.pbtxt")
+        } else {
+            include_str!("graphs/face_mesh_desktop_live.pbtxt")
+        });
+        config
+            .set_side_packet("num_faces", SidePacket::count("max_faces", self.max_faces)?)?
+            .set_side_packet("with_attention", SidePacket::Bool(self.refine_landmarks))?
+            .set_side_packet("use_prev_landmarks", SidePacket::Bool(self.running_mode.tracks()))?
+            .set_calculator_option(FACE_DETECTION_NODE, "min_score_thresh", FieldValue::confidence("min_detection_confidence", self.min_detection_confidence)?)
+            .set_calculator_option(FACE_TRACKING_NODE, "threshold", FieldValue::confidence("min_tracking_confidence", self.min_tracking_confidence)?);
+
+        let mut outputs = vec![Output {
+            type_: FeatureType::Faces,
+            name: "multi_face_landmarks".into(),
+        }];
+        if self.face_geometry {
+            outputs.push(Output {
+                type_: FeatureType::Classifications,
+                name: "multi_face_blendshapes".into(),
+            });
+            outputs.push(Output {
+                type_: FeatureType::Matrices,
+                name: "multi_face_geometry".into(),
+            });
+        }
+
+        let graph = Detector::try_from_config(&config, &self.resources, outputs)?;
+
+        Ok(FaceMeshDetector {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            face_geometry: self.face_geometry,
+            running_mode: self.running_mode,
+        })
+    }
+}
+
 pub struct FaceMeshDetector {
     graph: Detector,
+    buffer: LandmarkBuffer,
+    face_geometry: bool,
+    running_mode: RunningMode,
 }
 
 impl FaceMeshDetector {
     pub fn new() -> Self {
-        let graph = Detector::new(
-            include_str!("graphs/face_mesh_desktop_live.pbtxt"),
-            vec![Output {
-                type_: FeatureType::Faces,
-                name: "multi_face_landmarks".into(),
-            }],
-        );
+        Self::try_new().expect("Failed to create face mesh detector")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a face mesh detector.
+    pub fn builder() -> FaceMeshDetectorBuilder {
+        FaceMeshDetectorBuilder::default()
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
+    }
 
-        Self { graph }
+    /// Processes the input frame, returns a face mesh if detected.
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<FaceMesh> {
+        self.try_process(input).expect("Failed to process frame")
     }
 
     /// Processes the input frame, returns a face mesh if detected.
-    pub fn process(&mut self, input: &mut Mat) -> Vec<FaceMesh> {
-        let landmarks = self.graph.process(input);
-        let mut faces = vec![];
-
-        for face_landmarks in landmarks[0].iter() {
-            let mut face = FaceMesh::default();
-            face.data.copy_from_slice(&face_landmarks[..]);
-            faces.push(face);
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<FaceMesh>, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns a face mesh if detected.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<FaceMesh> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns a face mesh if detected.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<FaceMesh>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+        Ok(self.buffer.features::<FaceMesh>(0).cloned().collect())
+    }
+
+    /// Processes the input frame into `buffer`, returns the face meshes borrowed from it.
+    pub fn try_process_into<'b>(&mut self, input: &impl AsImageView, buffer: &'b mut LandmarkBuffer) -> Result<Features<'b, FaceMesh>, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at_into(input, timestamp, buffer)
+    }
+
+    /// Processes the input frame captured at `timestamp` into `buffer`, returns the face meshes
+    /// borrowed from it.
+    pub fn try_process_at_into<'b>(&mut self, input: &impl AsImageView, timestamp: Duration, buffer: &'b mut LandmarkBuffer) -> Result<Features<'b, FaceMesh>, Error> {
+        self.graph.try_process_at_into(input, timestamp, buffer)?;
+        Ok(buffer.features(0))
+    }
+
+    /// The blendshapes and head pose of the faces of the last processed frame, in the order of
+    /// the face meshes.
+    ///
+    /// Fails unless enabled with [FaceMeshDetectorBuilder::face_geometry].
+    pub fn face_geometry(&mut self) -> Result<Vec<FaceGeometry>, Error> {
+        if !self.face_geometry {
+            return Err(Error::InvalidInput("face geometry is not enabled, see FaceMeshDetectorBuilder::face_geometry".into()));
+        }
+
+        let blendshapes = self.graph.packet(1)?.and_then(|p| p.data.into_classifications()).unwrap_or_default();
+        let matrices = self.graph.packet(2)?.and_then(|p| p.data.into_matrices()).unwrap_or_default();
+        if blendshapes.len() != matrices.len() {
+            return Err(Error::Runtime(format!("got blendshapes for {} faces and head poses for {}", blendshapes.len(), matrices.len())));
         }
 
-        faces
+        Ok(blendshapes
+            .into_iter()
+            .zip(matrices)
+            .map(|(classifications, transformation_matrix)| {
+                let mut blendshapes = [0.0; NUM_BLENDSHAPES];
+                for c in classifications {
+                    if let Some(score) = usize::try_from(c.index).ok().and_then(|i| blendshapes.get_mut(i)) {
+                        *score = c.score;
+                    }
+                }
+                FaceGeometry { blendshapes, transformation_matrix }
+            })
+            .collect())
     }
 }
 
@@ -0,0 +1,277 @@
+//! Input frame validation and conversion.
+use opencv::{core, imgproc, prelude::*};
+
+use super::Error;
+
+/// Pixel layout of an input frame.
+///
+/// The C++ library works on continuous 8-bit RGB frames, every other format is converted before
+/// it is handed to the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// 8-bit, 3 channels, red first.
+    #[default]
+    Rgb,
+    /// 8-bit, 3 channels, blue first. The default layout of OpenCV captures.
+    Bgr,
+    /// 8-bit, 4 channels, red first.
+    Rgba,
+    /// 8-bit, 4 channels, blue first.
+    Bgra,
+    /// 8-bit, single channel.
+    Gray,
+    /// 8-bit, single channel YUV 4:2:0 with a full resolution Y plane followed by an interleaved
+    /// UV plane, so the buffer has `height * 3 / 2` rows.
+    Nv12,
+}
+
+impl PixelFormat {
+    /// Number of channels of a frame in this format.
+    pub fn channels(&self) -> i32 {
+        match self {
+            PixelFormat::Rgb | PixelFormat::Bgr => 3,
+            PixelFormat::Rgba | PixelFormat::Bgra => 4,
+            PixelFormat::Gray | PixelFormat::Nv12 => 1,
+        }
+    }
+
+    /// Number of buffer rows needed for an image with the given height.
+    fn rows(&self, height: i32) -> i32 {
+        match self {
+            PixelFormat::Nv12 => (height as i64 * 3 / 2) as i32,
+            _ => height,
+        }
+    }
+
+    /// The largest image height whose buffer rows can be counted in an `i32`.
+    fn max_height(&self) -> i32 {
+        match self {
+            PixelFormat::Nv12 => i32::MAX / 3 * 2,
+            _ => i32::MAX,
+        }
+    }
+
+    /// The OpenCV color conversion code to RGB, `None` if the format is RGB already.
+    fn rgb_conversion(&self) -> Option<i32> {
+        match self {
+            PixelFormat::Rgb => None,
+            PixelFormat::Bgr => Some(imgproc::COLOR_BGR2RGB),
+            PixelFormat::Rgba => Some(imgproc::COLOR_RGBA2RGB),
+            PixelFormat::Bgra => Some(imgproc::COLOR_BGRA2RGB),
+            PixelFormat::Gray => Some(imgproc::COLOR_GRAY2RGB),
+            PixelFormat::Nv12 => Some(imgproc::COLOR_YUV2RGB_NV12),
+        }
+    }
+
+    fn mat_type(&self) -> i32 {
+        match self.channels() {
+            1 => core::CV_8UC1,
+            3 => core::CV_8UC3,
+            _ => core::CV_8UC4,
+        }
+    }
+}
+
+/// A borrowed, possibly strided, 8-bit image buffer that can be fed into a detector.
+///
+/// Views built from a `Mat` don't carry a pixel format, the format configured on the detector is
+/// used for them. Views of raw buffers always state their format.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageView<'a> {
+    data: &'a [u8],
+    cols: i32,
+    rows: i32,
+    stride: usize,
+    channels: i32,
+    format: Option<PixelFormat>,
+}
+
+impl<'a> ImageView<'a> {
+    /// Creates a view of a raw pixel buffer.
+    ///
+    /// `stride` is the number of bytes between the starts of two rows, `height` is the image
+    /// height in pixels (the buffer of an NV12 image has `height * 3 / 2` rows).
+    pub fn new(data: &'a [u8], width: u32, height: u32, stride: usize, format: PixelFormat) -> Result<Self, Error> {
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > format.max_height() as u32 {
+            return Err(Error::InvalidInput(format!("invalid frame size {}x{}", width, height)));
+        }
+        let cols = width as i32;
+        let rows = format.rows(height as i32);
+        let row_len = cols as usize * format.channels() as usize;
+        if stride < row_len {
+            return Err(Error::InvalidInput(format!("stride {} is smaller than a row of {} bytes", stride, row_len)));
+        }
+        match (rows as usize - 1).checked_mul(stride).and_then(|n| n.checked_add(row_len)) {
+            Some(needed) if data.len() < needed => return Err(Error::InvalidInput(format!("buffer of {} bytes is too small, {} bytes needed", data.len(), needed))),
+            Some(_) => {}
+            None => return Err(Error::InvalidInput(format!("{} rows with a stride of {} bytes overflow the address space", rows, stride))),
+        }
+
+        Ok(Self {
+            data,
+            cols,
+            rows,
+            stride,
+            channels: format.channels(),
+            format: Some(format),
+        })
+    }
+
+    /// Creates a view of an 8-bit `Mat`. Sub-matrices are supported.
+    pub fn from_mat(mat: &'a Mat) -> Result<Self, Error> {
+        if mat.empty() {
+            return Err(Error::InvalidInput("frame is empty".into()));
+        }
+        if mat.depth() != core::CV_8U {
+            return Err(Error::InvalidInput(format!("expected an 8-bit frame, got depth {}", mat.depth())));
+        }
+
+        let cols = mat.cols();
+        let rows = mat.rows();
+        let channels = mat.channels();
+        // Elements are single bytes, so the step in elements is the stride in bytes.
+        let stride = mat.step1(0)?;
+        let len = (rows as usize - 1) * stride + cols as usize * channels as usize;
+        let data = unsafe { std::slice::from_raw_parts(mat.data(), len) };
+
+        Ok(Self {
+            data,
+            cols,
+            rows,
+            stride,
+            channels,
+            format: None,
+        })
+    }
+
+    /// Sets the pixel format of the view, overriding the format configured on the detector.
+    pub fn with_format(mut self, format: PixelFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Image width in pixels.
+    pub fn width(&self) -> u32 {
+        self.cols as u32
+    }
+
+    /// Number of rows in the buffer, this is the image height except for [PixelFormat::Nv12].
+    pub fn rows(&self) -> u32 {
+        self.rows as u32
+    }
+
+    /// The pixel format of the view, `None` if the detector's format is used.
+    pub fn format(&self) -> Option<PixelFormat> {
+        self.format
+    }
+}
+
+/// Types that can be used as detector input.
+pub trait AsImageView {
+    /// Returns a view of the image data.
+    fn as_image_view(&self) -> Result<ImageView<'_>, Error>;
+}
+
+impl AsImageView for ImageView<'_> {
+    fn as_image_view(&self) -> Result<ImageView<'_>, Error> {
+        Ok(*self)
+    }
+}
+
+impl AsImageView for Mat {
+    fn as_image_view(&self) -> Result<ImageView<'_>, Error> {
+        ImageView::from_mat(self)
+    }
+}
+
+#[cfg(feature = "image")]
+mod image_impls {
+    use super::*;
+
+    fn view<P: image::Pixel<Subpixel = u8>>(img: &image::ImageBuffer<P, Vec<u8>>, format: PixelFormat) -> Result<ImageView<'_>, Error> {
+        let stride = img.width() as usize * format.channels() as usize;
+        ImageView::new(img.as_raw(), img.width(), img.height(), stride, format)
+    }
+
+    impl AsImageView for image::RgbImage {
+        fn as_image_view(&self) -> Result<ImageView<'_>, Error> {
+            view(self, PixelFormat::Rgb)
+        }
+    }
+
+    impl AsImageView for image::RgbaImage {
+        fn as_image_view(&self) -> Result<ImageView<'_>, Error> {
+            view(self, PixelFormat::Rgba)
+        }
+    }
+
+    impl AsImageView for image::GrayImage {
+        fn as_image_view(&self) -> Result<ImageView<'_>, Error> {
+            view(self, PixelFormat::Gray)
+        }
+    }
+}
+
+/// A continuous 8-bit RGB frame, ready to be handed to the C++ library.
+pub(crate) struct RgbFrame<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+}
+
+/// Turns input frames into continuous 8-bit RGB frames.
+///
+/// The conversion buffer is kept between frames, so a steady stream of same sized frames is
+/// converted without reallocating.
+#[derive(Default)]
+pub(crate) struct FrameConverter {
+    pub(crate) format: PixelFormat,
+    buffer: Mat,
+}
+
+impl FrameConverter {
+    /// Width and height in pixels of the RGB frame `input` is converted to.
+    pub(crate) fn frame_size(&self, input: &ImageView) -> (i32, i32) {
+        match input.format.unwrap_or(self.format) {
+            PixelFormat::Nv12 => (input.cols, input.rows * 2 / 3),
+            _ => (input.cols, input.rows),
+        }
+    }
+
+    /// Validates `input` against its pixel format and returns a continuous RGB frame.
+    ///
+    /// Continuous RGB frames are passed through as is, everything else is converted or copied into
+    /// the internal buffer.
+    pub(crate) fn convert<'a>(&'a mut self, input: &ImageView<'a>) -> Result<RgbFrame<'a>, Error> {
+        let format = input.format.unwrap_or(self.format);
+        if input.channels != format.channels() {
+            return Err(Error::InvalidInput(format!("expected {} channels for {:?}, got {}", format.channels(), format, input.channels)));
+        }
+        if format == PixelFormat::Nv12 && (input.rows % 3 != 0 || input.cols % 2 != 0) {
+            return Err(Error::InvalidInput(format!("{}x{} is not a valid NV12 buffer size", input.cols, input.rows)));
+        }
+
+        let row_len = input.cols as usize * input.channels as usize;
+        if format == PixelFormat::Rgb && input.stride == row_len {
+            return Ok(RgbFrame {
+                data: input.data,
+                width: input.cols,
+                height: input.rows,
+            });
+        }
+
+        // The header only borrows the view's data, which is never written to.
+        let header = unsafe { Mat::new_rows_cols_with_data(input.rows, input.cols, format.mat_type(), input.data.as_ptr() as *mut std::ffi::c_void, input.stride) }?;
+        match format.rgb_conversion() {
+            // Strided RGB data (e.g. an ROI of a larger frame) is copied into a continuous buffer.
+            None => header.copy_to(&mut self.buffer)?,
+            Some(code) => imgproc::cvt_color(&header, &mut self.buffer, code, 0)?,
+        }
+
+        Ok(RgbFrame {
+            data: self.buffer.data_bytes()?,
+            width: self.buffer.cols(),
+            height: self.buffer.rows(),
+        })
+    }
+}
@@ -0,0 +1,462 @@
+//! Hand gesture recognition utilities.
+use std::path::{Path, PathBuf};
+
+use super::*;
+use hands::{HandDetection, HandDetector, HandLandmark, Handedness};
+
+/// Length of the landmark embedding, the 3D coordinates of the 21 hand landmarks.
+pub const EMBEDDING_SIZE: usize = 3 * hands::NUM_HAND_LANDMARKS;
+
+/// The gestures recognized without a custom classifier, named like MediaPipe's canned gestures.
+///
+/// They are a heuristic approximation of MediaPipe's canned gesture classifier, not its model:
+/// each finger is scored as extended or curled from its joint distances to the wrist and palm, and
+/// the thumb and index finger directions tell up from down. Train a custom classifier for
+/// gestures that need MediaPipe's accuracy, see [GestureRecognizerBuilder::classifier].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CannedGesture {
+    None,
+    ClosedFist,
+    OpenPalm,
+    PointingUp,
+    ThumbDown,
+    ThumbUp,
+    Victory,
+    ILoveYou,
+}
+
+impl CannedGesture {
+    /// MediaPipe's label of the gesture, e.g. `"Thumb_Up"`.
+    pub fn label(self) -> &'static str {
+        match self {
+            CannedGesture::None => "None",
+            CannedGesture::ClosedFist => "Closed_Fist",
+            CannedGesture::OpenPalm => "Open_Palm",
+            CannedGesture::PointingUp => "Pointing_Up",
+            CannedGesture::ThumbDown => "Thumb_Down",
+            CannedGesture::ThumbUp => "Thumb_Up",
+            CannedGesture::Victory => "Victory",
+            CannedGesture::ILoveYou => "ILoveYou",
+        }
+    }
+}
+
+/// A canned gesture and how well the hand matches it.
+#[derive(Debug, Clone, Copy)]
+pub struct Gesture {
+    pub gesture: CannedGesture,
+    /// Heuristic score between 0 and 1: the weakest of the finger poses the gesture requires,
+    /// each ramped from 0 to 1. It is not a probability and not comparable to the scores of
+    /// MediaPipe's canned gesture model.
+    pub score: f32,
+}
+
+/// A detected hand with its gestures.
+#[derive(Clone, Debug)]
+pub struct HandGesture {
+    pub hand: HandDetection,
+    /// Scores of all canned gestures, best first.
+    pub canned_gestures: Vec<Gesture>,
+    /// Gestures of the custom classifier, best first. Empty without one, see
+    /// [GestureRecognizerBuilder::classifier].
+    pub custom_gestures: Vec<Classification>,
+}
+
+impl HandGesture {
+    /// The best matching canned gesture.
+    pub fn gesture(&self) -> CannedGesture {
+        self.canned_gestures.first().map_or(CannedGesture::None, |g| g.gesture)
+    }
+}
+
+/// Configures a [GestureRecognizer].
+#[derive(Debug, Clone)]
+pub struct GestureRecognizerBuilder {
+    max_hands: usize,
+    running_mode: RunningMode,
+    min_detection_confidence: f32,
+    min_tracking_confidence: f32,
+    classifier: Option<PathBuf>,
+    label_map: Option<PathBuf>,
+    resources: Resources,
+}
+
+impl Default for GestureRecognizerBuilder {
+    fn default() -> Self {
+        Self {
+            max_hands: 2,
+            running_mode: RunningMode::default(),
+            min_detection_confidence: 0.5,
+            min_tracking_confidence: 0.5,
+            classifier: None,
+            label_map: None,
+            resources: Resources::default(),
+        }
+    }
+}
+
+impl GestureRecognizerBuilder {
+    /// Sets the maximum number of hands to detect and track, defaults to 2.
+    pub fn max_hands(mut self, max_hands: usize) -> Self {
+        self.max_hands = max_hands;
+        self
+    }
+
+    /// Sets how the frames passed to the recognizer relate to each other, defaults to
+    /// [RunningMode::LiveStream].
+    pub fn running_mode(mut self, running_mode: RunningMode) -> Self {
+        self.running_mode = running_mode;
+        self
+    }
+
+    /// Sets the minimum score of a hand detection for it to be tracked, defaults to 0.5.
+    pub fn min_detection_confidence(mut self, min_detection_confidence: f32) -> Self {
+        self.min_detection_confidence = min_detection_confidence;
+        self
+    }
+
+    /// Sets the minimum landmark score for the hand to be tracked into the next frame, defaults to
+    /// 0.5. Below it the detection model runs again.
+    pub fn min_tracking_confidence(mut self, min_tracking_confidence: f32) -> Self {
+        self.min_tracking_confidence = min_tracking_confidence;
+        self
+    }
+
+    /// Also runs the TFLite model at `model_path` on the [landmark_embedding] of each hand,
+    /// defaults to none.
+    ///
+    /// The model takes a float tensor of [EMBEDDING_SIZE] values and returns one score per
+    /// gesture.
+    pub fn classifier(mut self, model_path: impl AsRef<Path>) -> Self {
+        self.classifier = Some(model_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Reads the labels of the custom classifier from a file with one label per line, defaults to
+    /// unlabeled gestures.
+    pub fn label_map(mut self, label_map: impl AsRef<Path>) -> Self {
+        self.label_map = Some(label_map.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets where the hand detector reads its model files from, defaults to the directory set with
+    /// [set_resource_root_dir]. The custom classifier is read from its own path.
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the recognizer can not be created, see [GestureRecognizerBuilder::try_build].
+    pub fn build(self) -> GestureRecognizer {
+        self.try_build().expect("Failed to create gesture recognizer")
+    }
+
+    pub fn try_build(self) -> Result<GestureRecognizer, Error> {
+        let hands = HandDetector::builder()
+            .max_hands(self.max_hands)
+            .world_landmarks(true)
+            .running_mode(self.running_mode)
+            .min_detection_confidence(self.min_detection_confidence)
+            .min_tracking_confidence(self.min_tracking_confidence)
+            .resources(self.resources)
+            .try_build()?;
+
+        let classifier = match &self.classifier {
+            Some(model_path) => {
+                let path_str = |path: &Path| path.to_str().map(String::from).ok_or_else(|| Error::InvalidGraphConfig(format!("path {} is not valid UTF-8", path.display())));
+                let label_map = self.label_map.as_deref().map(path_str).transpose()?;
+
+                let mut config = GraphConfig::new(include_str!("graphs/gesture_classification_cpu.pbtxt"));
+                config
+                    .set_field("model_path", &[FieldValue::Str(path_str(model_path)?)])?
+                    .set_field("label_map_path", &label_map.into_iter().map(FieldValue::Str).collect::<Vec<_>>())?;
+
+                Some(
+                    Graph::builder(&config.to_text())
+                        .input_stream("embedding", PacketType::Matrix)
+                        .output_stream("gestures", PacketType::Classifications)
+                        .try_build()?,
+                )
+            }
+            None => None,
+        };
+
+        Ok(GestureRecognizer {
+            hands,
+            classifier,
+            classifier_timestamp: Duration::ZERO,
+        })
+    }
+}
+
+/// Detects hands and recognizes their gestures.
+pub struct GestureRecognizer {
+    hands: HandDetector,
+    classifier: Option<Graph>,
+    // Every hand is classified at its own timestamp, as a frame may have several.
+    classifier_timestamp: Duration,
+}
+
+impl GestureRecognizer {
+    pub fn new() -> Self {
+        Self::try_new().expect("Failed to create gesture recognizer")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a gesture recognizer.
+    pub fn builder() -> GestureRecognizerBuilder {
+        GestureRecognizerBuilder::default()
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.hands.set_pixel_format(format);
+    }
+
+    /// Processes the input frame, returns the detected hands and their gestures.
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<HandGesture> {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns the detected hands and their gestures.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<HandGesture>, Error> {
+        let hands = self.hands.try_process(input)?;
+        self.recognize(hands)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the detected hands and their
+    /// gestures.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<HandGesture> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the detected hands and their
+    /// gestures.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<HandGesture>, Error> {
+        let hands = self.hands.try_process_at(input, timestamp)?;
+        self.recognize(hands)
+    }
+
+    fn recognize(&mut self, hands: Vec<HandDetection>) -> Result<Vec<HandGesture>, Error> {
+        hands
+            .into_iter()
+            .map(|hand| {
+                let custom_gestures = match &mut self.classifier {
+                    Some(classifier) => {
+                        self.classifier_timestamp += Duration::from_micros(1);
+                        let embedding = Matrix {
+                            rows: 1,
+                            cols: EMBEDDING_SIZE,
+                            data: landmark_embedding(&hand),
+                        };
+                        classifier.send("embedding", &PacketData::Matrix(embedding), self.classifier_timestamp)?;
+
+                        // The classifier skips its output when no gesture passes the score
+                        // threshold, a missing packet means no custom gesture.
+                        classifier.wait_until_idle()?;
+                        let mut gestures = classifier.poll("gestures")?.and_then(|p| p.data.into_classifications()).and_then(|lists| lists.into_iter().next()).unwrap_or_default();
+                        gestures.sort_by(|a, b| b.score.total_cmp(&a.score));
+                        gestures
+                    }
+                    None => vec![],
+                };
+
+                Ok(HandGesture {
+                    canned_gestures: canned_gestures(&hand),
+                    custom_gestures,
+                    hand,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The input of custom gesture classifiers: the x, y and z of each landmark relative to the
+/// wrist, scaled so that the palm (wrist to middle finger base) has a length of one.
+///
+/// Uses the world landmarks if the hand has them. Left hands are mirrored, so the embedding of a
+/// gesture is the same for both hands.
+pub fn landmark_embedding(hand: &HandDetection) -> Vec<f32> {
+    let landmarks = &hand.world_landmarks.as_ref().unwrap_or(&hand.landmarks).data;
+    let wrist = &landmarks[HandLandmark::WRIST as usize];
+    let palm = distance(wrist, &landmarks[HandLandmark::MIDDLE_FINGER_MCP as usize]);
+    let scale = if palm > 0.0 { 1.0 / palm } else { 1.0 };
+    let mirror = if hand.handedness == Handedness::Left { -1.0 } else { 1.0 };
+
+    landmarks.iter().flat_map(|l| [(l.x - wrist.x) * scale * mirror, (l.y - wrist.y) * scale, (l.z - wrist.z) * scale]).collect()
+}
+
+/// Scores the canned gestures with heuristics on the finger poses, best first.
+fn canned_gestures(hand: &HandDetection) -> Vec<Gesture> {
+    use HandLandmark::*;
+
+    let landmarks = &hand.world_landmarks.as_ref().unwrap_or(&hand.landmarks).data;
+    let point = |landmark: HandLandmark| &landmarks[landmark as usize];
+
+    // A finger is extended when its tip is well beyond its middle joint, seen from the wrist.
+    let extended = |pip: HandLandmark, tip: HandLandmark| ramp(distance(point(WRIST), point(tip)) / distance(point(WRIST), point(pip)), 1.0, 1.3);
+    // The thumb is extended when its tip is away from the palm.
+    let thumb = ramp(distance(point(THUMB_TIP), point(PINKY_MCP)) / distance(point(THUMB_MCP), point(PINKY_MCP)), 1.0, 1.3);
+    let index = extended(INDEX_FINGER_PIP, INDEX_FINGER_TIP);
+    let middle = extended(MIDDLE_FINGER_PIP, MIDDLE_FINGER_TIP);
+    let ring = extended(RING_FINGER_PIP, RING_FINGER_TIP);
+    let pinky = extended(PINKY_PIP, PINKY_TIP);
+
+    // Directions are taken in the image, where y points down.
+    let image = |landmark: HandLandmark| &hand.landmarks.data[landmark as usize];
+    let upwards = |from: HandLandmark, to: HandLandmark| {
+        let (from, to) = (image(from), image(to));
+        let length = (to.x - from.x).hypot(to.y - from.y);
+        if length > 0.0 {
+            (from.y - to.y) / length
+        } else {
+            0.0
+        }
+    };
+    let thumb_up = upwards(THUMB_MCP, THUMB_TIP);
+    let index_up = upwards(INDEX_FINGER_MCP, INDEX_FINGER_TIP);
+
+    let all = |scores: &[f32]| scores.iter().copied().fold(1.0, f32::min);
+    let fingers_curled = all(&[1.0 - index, 1.0 - middle, 1.0 - ring, 1.0 - pinky]);
+
+    let mut gestures = vec![
+        (CannedGesture::ClosedFist, all(&[fingers_curled, 1.0 - thumb])),
+        (CannedGesture::OpenPalm, all(&[thumb, index, middle, ring, pinky])),
+        (CannedGesture::PointingUp, all(&[index, 1.0 - middle, 1.0 - ring, 1.0 - pinky, ramp(index_up, 0.4, 0.8)])),
+        (CannedGesture::ThumbDown, all(&[thumb, fingers_curled, ramp(-thumb_up, 0.4, 0.8)])),
+        (CannedGesture::ThumbUp, all(&[thumb, fingers_curled, ramp(thumb_up, 0.4, 0.8)])),
+        (CannedGesture::Victory, all(&[index, middle, 1.0 - ring, 1.0 - pinky])),
+        (CannedGesture::ILoveYou, all(&[thumb, index, 1.0 - middle, 1.0 - ring, pinky])),
+    ];
+    let best = gestures.iter().map(|(_, score)| *score).fold(0.0, f32::max);
+    gestures.push((CannedGesture::None, 1.0 - best));
+
+    let mut gestures = gestures.into_iter().map(|(gesture, score)| Gesture { gesture, score }).collect::<Vec<_>>();
+    gestures.sort_by(|a, b| b.score.total_cmp(&a.score));
+    gestures
+}
+
+/// Maps `value` linearly from `low..high` to `0..1`, clamped.
+fn ramp(value: f32, low: f32, high: f32) -> f32 {
+    if value.is_nan() {
+        return 0.0;
+    }
+    ((value - low) / (high - low)).clamp(0.0, 1.0)
+}
+
+fn distance(a: &Landmark, b: &Landmark) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HandLandmark::*;
+
+    /// The fingers of an upright right hand seen from the palm, wrist at the bottom and thumb on
+    /// the left.
+    const FINGERS: [(f32, [usize; 4]); 4] = [
+        (0.44, [INDEX_FINGER_MCP as usize, INDEX_FINGER_PIP as usize, INDEX_FINGER_DIP as usize, INDEX_FINGER_TIP as usize]),
+        (0.48, [MIDDLE_FINGER_MCP as usize, MIDDLE_FINGER_PIP as usize, MIDDLE_FINGER_DIP as usize, MIDDLE_FINGER_TIP as usize]),
+        (0.52, [RING_FINGER_MCP as usize, RING_FINGER_PIP as usize, RING_FINGER_DIP as usize, RING_FINGER_TIP as usize]),
+        (0.56, [PINKY_MCP as usize, PINKY_PIP as usize, PINKY_DIP as usize, PINKY_TIP as usize]),
+    ];
+
+    /// Thumb IP and tip positions.
+    const THUMB_OUT: [(f32, f32); 2] = [(0.37, 0.64), (0.32, 0.58)];
+    const THUMB_ACROSS: [(f32, f32); 2] = [(0.46, 0.66), (0.5, 0.65)];
+    const THUMB_RAISED: [(f32, f32); 2] = [(0.41, 0.55), (0.4, 0.4)];
+
+    /// A right hand in image coordinates, `extended` tells which of the index, middle, ring and
+    /// pinky fingers point up, the others are curled into the palm.
+    fn hand(extended: [bool; 4], thumb: [(f32, f32); 2]) -> HandDetection {
+        let mut landmarks = Hand::default();
+        let mut set = |landmark: usize, x: f32, y: f32| {
+            landmarks.data[landmark] = Landmark { x, y, ..Default::default() };
+        };
+        set(WRIST as usize, 0.5, 0.8);
+        set(THUMB_CMC as usize, 0.45, 0.75);
+        set(THUMB_MCP as usize, 0.42, 0.7);
+        set(THUMB_IP as usize, thumb[0].0, thumb[0].1);
+        set(THUMB_TIP as usize, thumb[1].0, thumb[1].1);
+        for ((x, joints), extended) in FINGERS.iter().zip(extended) {
+            let ys = if extended { [0.6, 0.5, 0.45, 0.4] } else { [0.6, 0.55, 0.6, 0.65] };
+            for (&joint, y) in joints.iter().zip(ys) {
+                set(joint, *x, y);
+            }
+        }
+
+        HandDetection {
+            landmarks,
+            world_landmarks: None,
+            handedness: Handedness::Right,
+            score: 1.0,
+        }
+    }
+
+    /// The same hand as a left hand, mirrored horizontally.
+    fn mirrored(hand: &HandDetection) -> HandDetection {
+        let mut left = hand.clone();
+        for l in &mut left.landmarks.data {
+            l.x = 1.0 - l.x;
+        }
+        left.handedness = Handedness::Left;
+        left
+    }
+
+    fn top_gesture(hand: &HandDetection) -> Gesture {
+        canned_gestures(hand)[0]
+    }
+
+    #[test]
+    fn canned_gestures_are_recognized() {
+        for (hand, expected) in [
+            (hand([false; 4], THUMB_ACROSS), CannedGesture::ClosedFist),
+            (hand([true; 4], THUMB_OUT), CannedGesture::OpenPalm),
+            (hand([false; 4], THUMB_RAISED), CannedGesture::ThumbUp),
+            (hand([true, false, false, false], THUMB_ACROSS), CannedGesture::PointingUp),
+            (hand([true, true, false, false], THUMB_ACROSS), CannedGesture::Victory),
+            (hand([true, false, false, true], THUMB_OUT), CannedGesture::ILoveYou),
+        ] {
+            for hand in [mirrored(&hand), hand] {
+                let top = top_gesture(&hand);
+                assert_eq!(top.gesture, expected, "{:?} hand: {:?}", hand.handedness, canned_gestures(&hand));
+                assert!(top.score > 0.9, "{:?}", top);
+            }
+        }
+    }
+
+    #[test]
+    fn all_canned_gestures_are_scored_best_first() {
+        let gestures = canned_gestures(&hand([true; 4], THUMB_OUT));
+        assert_eq!(gestures.len(), 8);
+        assert!(gestures.windows(2).all(|g| g[0].score >= g[1].score));
+        assert!(gestures.iter().all(|g| (0.0..=1.0).contains(&g.score)));
+        assert_eq!(gestures.iter().find(|g| g.gesture == CannedGesture::None).unwrap().score, 0.0);
+    }
+
+    #[test]
+    fn left_hand_embeddings_mirror_right_hands() {
+        let right = hand([true, true, false, false], THUMB_OUT);
+        let left = mirrored(&right);
+
+        let embedding = landmark_embedding(&right);
+        assert_eq!(embedding.len(), EMBEDDING_SIZE);
+        for (a, b) in embedding.iter().zip(landmark_embedding(&left)) {
+            assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+        }
+        // Relative to the wrist, and scaled so the palm has a length of one.
+        assert_eq!(&embedding[..3], &[0.0, 0.0, 0.0]);
+        let middle = MIDDLE_FINGER_MCP as usize * 3;
+        assert!((embedding[middle].hypot(embedding[middle + 1]) - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,338 @@
+//! Running arbitrary MediaPipe graphs.
+use super::*;
+use packet::RawPacket;
+
+/// A named graph stream and the type of its packets.
+#[derive(Debug, Clone)]
+struct Stream {
+    name: String,
+    type_: PacketType,
+}
+
+/// Declares the streams of a custom [Graph].
+///
+/// Every stream named here has to be an `input_stream` or `output_stream` of the graph config.
+#[derive(Debug, Clone)]
+pub struct GraphBuilder {
+    graph_config: String,
+    inputs: Vec<Stream>,
+    outputs: Vec<Stream>,
+}
+
+impl GraphBuilder {
+    pub fn new(graph_config: &str) -> Self {
+        Self {
+            graph_config: graph_config.into(),
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    /// Adds an input stream the graph reads packets of type `type_` from.
+    pub fn input_stream(mut self, name: &str, type_: PacketType) -> Self {
+        self.inputs.push(Stream { name: name.into(), type_ });
+        self
+    }
+
+    /// Adds an output stream the graph writes packets of type `type_` to.
+    pub fn output_stream(mut self, name: &str, type_: PacketType) -> Self {
+        self.outputs.push(Stream { name: name.into(), type_ });
+        self
+    }
+
+    /// Creates and starts the graph.
+    ///
+    /// Panics if the graph can not be created, see [GraphBuilder::try_build].
+    pub fn build(self) -> Graph {
+        self.try_build().expect("Failed to create graph")
+    }
+
+    /// Creates and starts the graph.
+    pub fn try_build(self) -> Result<Graph, Error> {
+        if self.inputs.is_empty() {
+            return Err(Error::InvalidGraphConfig("must specify at least one input stream".into()));
+        }
+        if self.inputs.len() > u8::MAX as usize || self.outputs.len() > u8::MAX as usize {
+            return Err(Error::InvalidGraphConfig(format!("at most {} input and output streams are supported", u8::MAX)));
+        }
+        let graph_config = CString::new(self.graph_config).map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+
+        let stream_names = self
+            .inputs
+            .iter()
+            .chain(&self.outputs)
+            .map(|s| CString::new(s.name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+        let raw_streams = self
+            .inputs
+            .iter()
+            .chain(&self.outputs)
+            .zip(&stream_names)
+            .map(|(s, name)| mediagraph_Stream {
+                type_: s.type_.to_raw(),
+                name: name.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+        let (raw_inputs, raw_outputs) = raw_streams.split_at(self.inputs.len());
+
+        let graph: *mut mediagraph_Graph = unsafe { mediagraph_Graph_Create(graph_config.as_ptr(), raw_inputs.as_ptr(), raw_inputs.len() as u8, raw_outputs.as_ptr(), raw_outputs.len() as u8) };
+
+        if graph.is_null() {
+            return Err(Error::from_last_status(Error::InvalidGraphConfig).unwrap_or_else(|| Error::InvalidGraphConfig("graph could not be created".into())));
+        }
+
+        Ok(Graph {
+            graph,
+            clocks: self.inputs.iter().map(|_| PacketClock::new()).collect(),
+            inputs: self.inputs,
+            outputs: self.outputs,
+            stream_names,
+            converter: FrameConverter::default(),
+        })
+    }
+}
+
+/// A MediaPipe graph with custom input and output streams.
+///
+/// Packets are sent to the input streams with explicit timestamps, which have to be strictly
+/// increasing per stream. The graph runs asynchronously, outputs are collected with
+/// [Graph::poll] or [Graph::receive].
+pub struct Graph {
+    graph: *mut mediagraph_Graph,
+    inputs: Vec<Stream>,
+    outputs: Vec<Stream>,
+    // Kept alive as long as the graph, which may reference them.
+    stream_names: Vec<CString>,
+    converter: FrameConverter,
+    clocks: Vec<PacketClock>,
+}
+
+impl Graph {
+    /// Starts declaring a graph with the given config.
+    pub fn builder(graph_config: &str) -> GraphBuilder {
+        GraphBuilder::new(graph_config)
+    }
+
+    /// Sets the pixel format of the images sent to the graph, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.converter.format = format;
+    }
+
+    /// The pixel format of the images sent to the graph.
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.converter.format
+    }
+
+    fn input_index(&self, name: &str, type_: PacketType) -> Result<usize, Error> {
+        let index = self.inputs.iter().position(|s| s.name == name).ok_or_else(|| Error::InvalidInput(format!("no input stream named {}", name)))?;
+        if self.inputs[index].type_ != type_ {
+            return Err(Error::InvalidInput(format!("input stream {} takes {:?} packets, not {:?}", name, self.inputs[index].type_, type_)));
+        }
+        Ok(index)
+    }
+
+    fn output_index(&self, name: &str) -> Result<usize, Error> {
+        self.outputs.iter().position(|s| s.name == name).ok_or_else(|| Error::InvalidInput(format!("no output stream named {}", name)))
+    }
+
+    /// Sends an image captured at `timestamp` to the input stream `stream`.
+    pub fn send_image(&mut self, stream: &str, input: &impl AsImageView, timestamp: Duration) -> Result<(), Error> {
+        let index = self.input_index(stream, PacketType::Image)?;
+        let view = input.as_image_view()?;
+        let frame = self.converter.convert(&view)?;
+
+        let mut raw: mediagraph_Packet = unsafe { std::mem::zeroed() };
+        raw.type_ = PacketType::Image.to_raw();
+        raw.image = mediagraph_Image {
+            data: frame.data.as_ptr() as *mut u8,
+            width: frame.width,
+            height: frame.height,
+            width_step: frame.width * 3,
+            channels: 3,
+            is_float: false,
+        };
+        // Advanced last, a packet that fails to convert doesn't use up its timestamp.
+        raw.timestamp_us = self.clocks[index].advance(timestamp)?;
+
+        // The library only reads the packet, its data is copied into the graph's input packet.
+        unsafe { mediagraph_Graph_AddPacket(self.graph, index as u8, &raw) };
+
+        match Error::from_last_status(Error::Runtime) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends a packet with the given `timestamp` to the input stream `stream`.
+    pub fn send(&mut self, stream: &str, data: &PacketData, timestamp: Duration) -> Result<(), Error> {
+        if let PacketData::Image(image) = data {
+            return self.send_image(stream, image, timestamp);
+        }
+
+        let index = self.input_index(stream, data.packet_type())?;
+        let mut buffers = RawPacket::default();
+        let mut raw = buffers.as_raw(data)?;
+        raw.timestamp_us = self.clocks[index].advance(timestamp)?;
+
+        // The library only reads the packet, its data is copied into the graph's input packet.
+        unsafe { mediagraph_Graph_AddPacket(self.graph, index as u8, &raw) };
+
+        match Error::from_last_status(Error::Runtime) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn next_packet(&mut self, stream: &str, wait: bool) -> Result<Option<Packet>, Error> {
+        let index = self.output_index(stream)?;
+        let mut raw: mediagraph_Packet = unsafe { std::mem::zeroed() };
+        let available = unsafe { mediagraph_Graph_Poll(self.graph, index as u8, wait, &mut raw) };
+
+        if let Some(e) = Error::from_last_status(Error::Runtime) {
+            return Err(e);
+        }
+        if !available {
+            return Ok(None);
+        }
+        if raw.type_ != self.outputs[index].type_.to_raw() {
+            return Err(Error::Runtime(format!("output stream {} returned a packet of type {}", stream, raw.type_)));
+        }
+
+        Packet::from_raw(&raw).map(Some)
+    }
+
+    /// Returns the next packet of the output stream `stream` if one is queued, without blocking.
+    pub fn poll(&mut self, stream: &str) -> Result<Option<Packet>, Error> {
+        self.next_packet(stream, false)
+    }
+
+    /// Blocks until the output stream `stream` has a packet and returns it.
+    ///
+    /// Graphs skip outputs they have nothing to report for (e.g. no landmarks without a detection),
+    /// so use [Graph::wait_until_idle] and [Graph::poll] for streams that are not always filled.
+    pub fn receive(&mut self, stream: &str) -> Result<Packet, Error> {
+        self.next_packet(stream, true)?.ok_or_else(|| Error::Runtime(format!("output stream {} is closed", stream)))
+    }
+
+    /// Blocks until the graph processed all packets sent so far.
+    pub fn wait_until_idle(&mut self) -> Result<(), Error> {
+        unsafe { mediagraph_Graph_WaitUntilIdle(self.graph) };
+
+        match Error::from_last_status(Error::Runtime) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Graph {
+    fn drop(&mut self) {
+        unsafe {
+            mediagraph_Graph_Destroy(self.graph);
+        }
+    }
+}
+
+// SAFETY: Same as for `Detector`, the graph may be driven from any thread but the packet buffers
+// returned by `Poll` are only valid until the next poll of the same stream.
+unsafe impl Send for Graph {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_graph(type_: PacketType) -> Graph {
+        Graph::builder("").input_stream("input", type_).output_stream("output", type_).build()
+    }
+
+    #[test]
+    fn matrix_packets_round_trip() {
+        let mut graph = echo_graph(PacketType::Matrix);
+        let matrix = Matrix {
+            rows: 2,
+            cols: 3,
+            data: vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+        };
+        graph.send("input", &PacketData::Matrix(matrix.clone()), Duration::from_millis(10)).unwrap();
+
+        let packet = graph.receive("output").unwrap();
+        assert_eq!(packet.timestamp, Duration::from_millis(10));
+        match packet.data {
+            PacketData::Matrix(received) => assert_eq!(received, matrix),
+            data => panic!("expected a matrix, got {:?}", data),
+        }
+        assert!(graph.poll("output").unwrap().is_none());
+    }
+
+    #[test]
+    fn detection_packets_round_trip() {
+        let mut graph = echo_graph(PacketType::Detections);
+        let detections = vec![
+            Detection {
+                score: 0.9,
+                label_id: 1,
+                label: "cat".into(),
+                bbox: BoundingBox {
+                    xmin: 0.1,
+                    ymin: 0.2,
+                    width: 0.3,
+                    height: 0.4,
+                },
+                keypoints: vec![Keypoint { x: 0.5, y: 0.6 }],
+            },
+            Detection {
+                score: 0.5,
+                label_id: 2,
+                label: String::new(),
+                bbox: BoundingBox::default(),
+                keypoints: vec![Keypoint { x: 0.7, y: 0.8 }, Keypoint { x: 0.9, y: 1.0 }],
+            },
+        ];
+        graph.send("input", &PacketData::Detections(detections.clone()), Duration::from_millis(10)).unwrap();
+
+        assert_eq!(graph.receive("output").unwrap().data.into_detections(), Some(detections));
+    }
+
+    #[test]
+    fn image_packets_round_trip() {
+        let mut graph = echo_graph(PacketType::Image);
+        let (width, height) = (4, 3);
+        let input = (0..width * height * 3).map(|i| i as u8).collect::<Vec<_>>();
+        let view = ImageView::new(&input, width, height, width as usize * 3, PixelFormat::Rgb).unwrap();
+        graph.send_image("input", &view, Duration::from_millis(10)).unwrap();
+
+        let packet = graph.receive("output").unwrap();
+        assert_eq!(packet.timestamp, Duration::from_millis(10));
+        let image = packet.data.into_image().unwrap();
+        assert_eq!((image.cols(), image.rows()), (width as i32, height as i32));
+        assert_eq!(image.typ(), opencv::core::CV_8UC3);
+        assert_eq!(image.data_bytes().unwrap(), &input[..]);
+    }
+
+    #[test]
+    fn timestamps_must_increase() {
+        let mut graph = echo_graph(PacketType::Floats);
+        let send = |graph: &mut Graph, value: f32, ms: u64| graph.send("input", &PacketData::Floats(vec![value]), Duration::from_millis(ms));
+
+        send(&mut graph, 1.0, 10).unwrap();
+        assert!(matches!(send(&mut graph, 2.0, 10), Err(Error::InvalidTimestamp(_))));
+        assert!(matches!(send(&mut graph, 3.0, 5), Err(Error::InvalidTimestamp(_))));
+        send(&mut graph, 4.0, 11).unwrap();
+
+        // Rejected packets never reach the graph.
+        assert_eq!(graph.receive("output").unwrap().data.into_floats(), Some(vec![1.0]));
+        let packet = graph.receive("output").unwrap();
+        assert_eq!((packet.timestamp, packet.data.into_floats()), (Duration::from_millis(11), Some(vec![4.0])));
+        assert!(graph.poll("output").unwrap().is_none());
+    }
+
+    #[test]
+    fn packets_of_the_wrong_type_are_rejected() {
+        let mut graph = echo_graph(PacketType::Floats);
+        assert!(matches!(graph.send("input", &PacketData::Rects(vec![]), Duration::from_millis(10)), Err(Error::InvalidInput(_))));
+        assert!(matches!(graph.send("missing", &PacketData::Floats(vec![]), Duration::from_millis(10)), Err(Error::InvalidInput(_))));
+        // The failed sends didn't use up the timestamp.
+        graph.send("input", &PacketData::Floats(vec![]), Duration::from_millis(10)).unwrap();
+    }
+}
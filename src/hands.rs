@@ -28,35 +28,246 @@ pub enum HandLandmark {
     PINKY_TIP = 20,
 }
 
+/// Which hand was detected.
+///
+/// MediaPipe assumes mirrored (selfie camera) input, flip other frames horizontally to get the
+/// handedness of the person in the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handedness {
+    Left,
+    Right,
+}
+
+impl Handedness {
+    fn from_classification(classification: &Classification) -> Result<Self, Error> {
+        match (classification.label.as_str(), classification.index) {
+            ("Left", _) | ("", 0) => Ok(Handedness::Left),
+            ("Right", _) | ("", 1) => Ok(Handedness::Right),
+            (label, index) => Err(Error::Runtime(format!("unknown handedness {:?} ({})", label, index))),
+        }
+    }
+}
+
+/// A detected hand with its handedness.
+#[derive(Clone, Debug)]
+pub struct HandDetection {
+    /// Landmarks in normalized image coordinates.
+    pub landmarks: Hand,
+    /// Landmarks in meters, if enabled with [HandDetectorBuilder::world_landmarks].
+    pub world_landmarks: Option<Hand>,
+    pub handedness: Handedness,
+    /// Confidence of the handedness, between 0 and 1.
+    pub score: f32,
+}
+
+pub(crate) const HAND_LANDMARK_FULL_MODEL: &str = "mediapipe/modules/hand_landmark/hand_landmark_full.tflite";
+
+/// Expanded names of the calculators applying the confidence thresholds of the hand subgraphs.
+const PALM_DETECTION_NODE: &str = "palmdetectioncpu__TensorsToDetectionsCalculator";
+const HAND_TRACKING_NODE: &str = "handlandmarkcpu__ThresholdingCalculator";
+
+/// Configures a [HandDetector].
+#[derive(Debug, Clone)]
+pub struct HandDetectorBuilder {
+    max_hands: usize,
+    world_landmarks: bool,
+    running_mode: RunningMode,
+    model_complexity: ModelComplexity,
+    min_detection_confidence: f32,
+    min_tracking_confidence: f32,
+    resources: Resources,
+}
+
+impl Default for HandDetectorBuilder {
+    fn default() -> Self {
+        Self {
+            max_hands: 2,
+            world_landmarks: false,
+            running_mode: RunningMode::default(),
+            model_complexity: ModelComplexity::default(),
+            min_detection_confidence: 0.5,
+            min_tracking_confidence: 0.5,
+            resources: Resources::default(),
+        }
+    }
+}
+
+impl HandDetectorBuilder {
+    /// Sets the maximum number of hands to detect and track, defaults to 2.
+    pub fn max_hands(mut self, max_hands: usize) -> Self {
+        self.max_hands = max_hands;
+        self
+    }
+
+    /// Also returns the landmarks in world coordinates, defaults to `false`.
+    pub fn world_landmarks(mut self, world_landmarks: bool) -> Self {
+        self.world_landmarks = world_landmarks;
+        self
+    }
+
+    /// Sets how the frames passed to the detector relate to each other, defaults to
+    /// [RunningMode::LiveStream].
+    pub fn running_mode(mut self, running_mode: RunningMode) -> Self {
+        self.running_mode = running_mode;
+        self
+    }
+
+    /// Sets the size of the landmark model, defaults to [ModelComplexity::Full].
+    ///
+    /// There is no heavy hand landmark model, [ModelComplexity::Heavy] fails to build.
+    pub fn model_complexity(mut self, model_complexity: ModelComplexity) -> Self {
+        self.model_complexity = model_complexity;
+        self
+    }
+
+    /// Sets the minimum score of a hand detection for it to be tracked, defaults to 0.5.
+    pub fn min_detection_confidence(mut self, min_detection_confidence: f32) -> Self {
+        self.min_detection_confidence = min_detection_confidence;
+        self
+    }
+
+    /// Sets the minimum landmark score for the hand to be tracked into the next frame, defaults to
+    /// 0.5. Below it the detection model runs again.
+    pub fn min_tracking_confidence(mut self, min_tracking_confidence: f32) -> Self {
+        self.min_tracking_confidence = min_tracking_confidence;
+        self
+    }
+
+    /// Sets where the model files are read from, defaults to the directory set with
+    /// [set_resource_root_dir].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [HandDetectorBuilder::try_build].
+    pub fn build(self) -> HandDetector {
+        self.try_build().expect("Failed to create hand detector")
+    }
+
+    pub fn try_build(self) -> Result<HandDetector, Error> {
+        let models = match self.model_complexity {
+            ModelComplexity::Lite => ["mediapipe/modules/palm_detection/palm_detection_lite.tflite", "mediapipe/modules/hand_landmark/hand_landmark_lite.tflite"],
+            ModelComplexity::Full => ["mediapipe/modules/palm_detection/palm_detection_full.tflite", HAND_LANDMARK_FULL_MODEL],
+            ModelComplexity::Heavy => return Err(Error::InvalidGraphConfig("hands have no heavy landmark model".into())),
+        };
+        self.resources.check(&models)?;
+
+        let mut config = GraphConfig::new(include_str!("graphs/hand_tracking_desktop_live.pbtxt"));
+        config
+            .set_side_packet("num_hands", SidePacket::count("max_hands", self.max_hands)?)?
+            .set_side_packet("model_complexity", self.model_complexity.side_packet())?
+            .set_side_packet("use_prev_landmarks", SidePacket::Bool(self.running_mode.tracks()))?
+            .set_calculator_option(PALM_DETECTION_NODE, "min_score_thresh", FieldValue::confidence("min_detection_confidence", self.min_detection_confidence)?)
+            .set_calculator_option(HAND_TRACKING_NODE, "threshold", FieldValue::confidence("min_tracking_confidence", self.min_tracking_confidence)?);
+
+        let mut outputs = vec![
+            Output {
+                type_: FeatureType::Hands,
+                name: "hand_landmarks".into(),
+            },
+            Output {
+                type_: FeatureType::Classifications,
+                name: "handedness".into(),
+            },
+        ];
+        if self.world_landmarks {
+            outputs.push(Output {
+                type_: FeatureType::HandsWorld,
+                name: "hand_world_landmarks".into(),
+            });
+        }
+
+        let graph = Detector::try_from_config(&config, &self.resources, outputs)?;
+
+        Ok(HandDetector {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            world_landmarks: self.world_landmarks,
+            running_mode: self.running_mode,
+        })
+    }
+}
+
 pub struct HandDetector {
     graph: Detector,
+    buffer: LandmarkBuffer,
+    world_landmarks: bool,
+    running_mode: RunningMode,
 }
 
 impl HandDetector {
     pub fn new() -> Self {
-        let graph = Detector::new(
-            include_str!("graphs/hand_tracking_desktop_live.pbtxt"),
-            vec![Output {
-                type_: FeatureType::Hands,
-                name: "hand_landmarks".into(),
-            }],
-        );
+        Self::try_new().expect("Failed to create hand detector")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a hand detector.
+    pub fn builder() -> HandDetectorBuilder {
+        HandDetectorBuilder::default()
+    }
 
-        Self { graph }
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
     }
 
     /// Processes the input frame, returns a list of hands
-    pub fn process(&mut self, input: &mut Mat) -> Vec<Hand> {
-        let result = self.graph.process(input);
-        let mut hands = vec![];
-
-        for hand_landmarks in result[0].iter() {
-            let mut hand = Hand::default();
-            hand.data.copy_from_slice(&hand_landmarks[..]);
-            hands.push(hand);
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<HandDetection> {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns a list of hands
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<HandDetection>, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns a list of hands
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<HandDetection> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns a list of hands
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<HandDetection>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+
+        let handedness = self.graph.packet(1)?.and_then(|p| p.data.into_classifications()).unwrap_or_default();
+        if handedness.len() != self.buffer.count(0) {
+            return Err(Error::Runtime(format!("got {} hands but {} handedness results", self.buffer.count(0), handedness.len())));
         }
 
-        hands
+        let mut world_hands = if self.world_landmarks { Some(self.buffer.features::<Hand>(2)) } else { None };
+        self.buffer
+            .features::<Hand>(0)
+            .zip(handedness)
+            .map(|(hand, classifications)| {
+                // The first classification is the most likely hand.
+                let best = classifications.first().ok_or_else(|| Error::Runtime("empty handedness result".into()))?;
+                Ok(HandDetection {
+                    landmarks: hand.clone(),
+                    world_landmarks: world_hands.as_mut().and_then(|w| w.next()).cloned(),
+                    handedness: Handedness::from_classification(best)?,
+                    score: best.score,
+                })
+            })
+            .collect()
+    }
+
+    /// Processes the input frame into `buffer`, returns the hand landmarks borrowed from it.
+    pub fn try_process_into<'b>(&mut self, input: &impl AsImageView, buffer: &'b mut LandmarkBuffer) -> Result<Features<'b, Hand>, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at_into(input, timestamp, buffer)
+    }
+
+    /// Processes the input frame captured at `timestamp` into `buffer`, returns the hand
+    /// landmarks borrowed from it.
+    pub fn try_process_at_into<'b>(&mut self, input: &impl AsImageView, timestamp: Duration, buffer: &'b mut LandmarkBuffer) -> Result<Features<'b, Hand>, Error> {
+        self.graph.try_process_at_into(input, timestamp, buffer)?;
+        Ok(buffer.features(0))
     }
 }
 
@@ -72,13 +283,43 @@ pub struct HandVisualizer {
 
 impl HandVisualizer {
     pub fn new() -> Self {
-        let graph = Effect::new(include_str!("graphs/hand_tracking_desktop_live.pbtxt"), "output_video");
+        Self::try_new().expect("Failed to create hand visualizer")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        let graph = Effect::try_new(include_str!("graphs/hand_tracking_desktop_live.pbtxt"), "output_video")?;
 
-        Self { graph }
+        Ok(Self { graph })
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
     }
 
     /// Processes the input frame, returns the output frame.
-    pub fn process(&mut self, input: &mut Mat) -> Mat {
+    pub fn process(&mut self, input: &impl AsImageView) -> Mat {
         self.graph.process(input)
     }
+
+    /// Processes the input frame, returns the output frame.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Mat, Error> {
+        self.graph.try_process(input)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the output frame.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Mat {
+        self.graph.process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the output frame.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Mat, Error> {
+        self.graph.try_process_at(input, timestamp)
+    }
+}
+
+impl Default for HandVisualizer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
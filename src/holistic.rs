@@ -1,21 +1,112 @@
 //! Hollistic detection utilities.
 use super::*;
+use face_mesh::FACE_LANDMARK_MODEL;
+use hands::{HandLandmark, HAND_LANDMARK_FULL_MODEL};
+use pose::{pose_landmark_model, PoseLandmark, POSE_DETECTION_MODEL, POSE_DETECTION_NODE, POSE_TRACKING_NODE};
 
-pub struct HolisticDetector {
-    graph: Detector,
+/// Index of the nose tip in a [FaceMesh].
+const FACE_NOSE_TIP: usize = 1;
+
+/// How far a face or hand may be from the matching pose landmark, relative to the torso length.
+const MAX_ASSOCIATION_DISTANCE: f32 = 0.5;
+
+/// Configures a [HolisticDetector].
+#[derive(Debug, Clone)]
+pub struct HolisticDetectorBuilder {
+    enable_segmentation: bool,
+    running_mode: RunningMode,
+    model_complexity: ModelComplexity,
+    smooth_landmarks: bool,
+    min_detection_confidence: f32,
+    min_tracking_confidence: f32,
+    resources: Resources,
 }
 
-#[derive(Clone, Debug)]
-pub struct HolisticDetection {
-    pub pose: Option<Pose>,
-    pub face: Option<FaceMesh>,
-    pub left_hand: Option<Hand>,
-    pub right_hand: Option<Hand>,
+impl Default for HolisticDetectorBuilder {
+    fn default() -> Self {
+        Self {
+            enable_segmentation: false,
+            running_mode: RunningMode::default(),
+            model_complexity: ModelComplexity::default(),
+            smooth_landmarks: true,
+            min_detection_confidence: 0.5,
+            min_tracking_confidence: 0.5,
+            resources: Resources::default(),
+        }
+    }
 }
 
-impl HolisticDetector {
-    pub fn new() -> Self {
-        let outputs = vec![
+impl HolisticDetectorBuilder {
+    /// Enables the segmentation model and returns its mask, defaults to `false`.
+    pub fn enable_segmentation(mut self, enable_segmentation: bool) -> Self {
+        self.enable_segmentation = enable_segmentation;
+        self
+    }
+
+    /// Sets how the frames passed to the detector relate to each other, defaults to
+    /// [RunningMode::LiveStream].
+    pub fn running_mode(mut self, running_mode: RunningMode) -> Self {
+        self.running_mode = running_mode;
+        self
+    }
+
+    /// Sets the size of the pose landmark model, defaults to [ModelComplexity::Full].
+    pub fn model_complexity(mut self, model_complexity: ModelComplexity) -> Self {
+        self.model_complexity = model_complexity;
+        self
+    }
+
+    /// Filters the landmarks across frames to reduce jitter, defaults to `true`. Has no effect
+    /// with [RunningMode::Image].
+    pub fn smooth_landmarks(mut self, smooth_landmarks: bool) -> Self {
+        self.smooth_landmarks = smooth_landmarks;
+        self
+    }
+
+    /// Sets the minimum score of a pose detection for it to be tracked, defaults to 0.5.
+    pub fn min_detection_confidence(mut self, min_detection_confidence: f32) -> Self {
+        self.min_detection_confidence = min_detection_confidence;
+        self
+    }
+
+    /// Sets the minimum landmark score for the pose to be tracked into the next frame, defaults to
+    /// 0.5. Below it the detection model runs again.
+    pub fn min_tracking_confidence(mut self, min_tracking_confidence: f32) -> Self {
+        self.min_tracking_confidence = min_tracking_confidence;
+        self
+    }
+
+    /// Sets where the model files are read from, defaults to the directory set with
+    /// [set_resource_root_dir].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [HolisticDetectorBuilder::try_build].
+    pub fn build(self) -> HolisticDetector {
+        self.try_build().expect("Failed to create holistic detector")
+    }
+
+    pub fn try_build(self) -> Result<HolisticDetector, Error> {
+        self.resources.check(&[
+            POSE_DETECTION_MODEL,
+            pose_landmark_model(self.model_complexity),
+            "mediapipe/modules/holistic_landmark/hand_recrop.tflite",
+            HAND_LANDMARK_FULL_MODEL,
+            FACE_LANDMARK_MODEL,
+        ])?;
+
+        let mut config = GraphConfig::new(include_str!("graphs/holistic_tracking_cpu.pbtxt"));
+        config
+            .set_side_packet("enable_segmentation", SidePacket::Bool(self.enable_segmentation))?
+            .set_side_packet("model_complexity", self.model_complexity.side_packet())?
+            .set_side_packet("use_prev_landmarks", SidePacket::Bool(self.running_mode.tracks()))?
+            .set_side_packet("smooth_landmarks", SidePacket::Bool(self.smooth_landmarks && self.running_mode.tracks()))?
+            .set_calculator_option(POSE_DETECTION_NODE, "min_score_thresh", FieldValue::confidence("min_detection_confidence", self.min_detection_confidence)?)
+            .set_calculator_option(POSE_TRACKING_NODE, "threshold", FieldValue::confidence("min_tracking_confidence", self.min_tracking_confidence)?);
+
+        let mut outputs = vec![
             Output {
                 type_: FeatureType::Pose,
                 name: "pose_landmarks".into(),
@@ -33,46 +124,91 @@ impl HolisticDetector {
                 name: "right_hand_landmarks".into(),
             },
         ];
+        if self.enable_segmentation {
+            outputs.push(Output {
+                type_: FeatureType::Image,
+                name: "segmentation_mask".into(),
+            });
+        }
 
-        let graph = Detector::new(include_str!("graphs/holistic_tracking_cpu.pbtxt"), outputs);
+        let graph = Detector::try_from_config(&config, &self.resources, outputs)?;
 
-        Self { graph }
+        Ok(HolisticDetector {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            enable_segmentation: self.enable_segmentation,
+            running_mode: self.running_mode,
+        })
+    }
+}
+
+pub struct HolisticDetector {
+    graph: Detector,
+    buffer: LandmarkBuffer,
+    enable_segmentation: bool,
+    running_mode: RunningMode,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HolisticDetection {
+    pub pose: Option<Pose>,
+    pub face: Option<FaceMesh>,
+    pub left_hand: Option<Hand>,
+    pub right_hand: Option<Hand>,
+    /// Per-pixel confidence that the pixel belongs to the person (`CV_32FC1`, 0 to 1), if
+    /// enabled with [HolisticDetectorBuilder::enable_segmentation]. Always `None` for multiple
+    /// people.
+    pub segmentation_mask: Option<Mat>,
+}
+
+impl HolisticDetector {
+    pub fn new() -> Self {
+        Self::try_new().expect("Failed to create holistic detector")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a holistic detector.
+    pub fn builder() -> HolisticDetectorBuilder {
+        HolisticDetectorBuilder::default()
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
     }
 
     /// Processes the input frame, returns landmarks if detected
-    pub fn process(&mut self, input: &mut Mat) -> HolisticDetection {
-        let landmarks = self.graph.process(input);
-
-        let mut pose = None;
-        let mut face = None;
-        let mut left_hand = None;
-        let mut right_hand = None;
-
-        if !landmarks[0].is_empty() {
-            let mut p = Pose::default();
-            p.data.copy_from_slice(&landmarks[0][0][..]);
-            pose = Some(p);
-        }
+    pub fn process(&mut self, input: &impl AsImageView) -> HolisticDetection {
+        self.try_process(input).expect("Failed to process frame")
+    }
 
-        if !landmarks[1].is_empty() {
-            let mut f = FaceMesh::default();
-            f.data.copy_from_slice(&landmarks[1][0][..]);
-            face = Some(f);
-        }
+    /// Processes the input frame, returns landmarks if detected
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<HolisticDetection, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at(input, timestamp)
+    }
 
-        if !landmarks[2].is_empty() {
-            let mut l = Hand::default();
-            l.data.copy_from_slice(&landmarks[2][0][..]);
-            left_hand = Some(l);
-        }
+    /// Processes the input frame captured at `timestamp`, returns landmarks if detected
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> HolisticDetection {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
 
-        if !landmarks[3].is_empty() {
-            let mut r = Hand::default();
-            r.data.copy_from_slice(&landmarks[3][0][..]);
-            right_hand = Some(r);
-        }
+    /// Processes the input frame captured at `timestamp`, returns landmarks if detected
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<HolisticDetection, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
 
-        HolisticDetection { pose, face, left_hand, right_hand }
+        let segmentation_mask = if self.enable_segmentation { self.graph.packet(4)?.and_then(|p| p.data.into_image()) } else { None };
+
+        Ok(HolisticDetection {
+            pose: self.buffer.first::<Pose>(0).cloned(),
+            face: self.buffer.first::<FaceMesh>(1).cloned(),
+            left_hand: self.buffer.first::<Hand>(2).cloned(),
+            right_hand: self.buffer.first::<Hand>(3).cloned(),
+            segmentation_mask,
+        })
     }
 }
 
@@ -82,12 +218,41 @@ impl Default for HolisticDetector {
     }
 }
 
+/// Detects the pose, face and hands of multiple people.
+///
+/// Faces and hands are attributed to the pose whose nose or wrist they are closest to. The ones
+/// that are too far from every pose are returned as separate detections without a pose.
 pub struct MultiPersonHolisticDetector {
     graph: Detector,
+    buffer: LandmarkBuffer,
 }
 
 impl MultiPersonHolisticDetector {
     pub fn new() -> Self {
+        Self::try_new().expect("Failed to create multi person holistic detector")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::try_with_resources(Resources::default())
+    }
+
+    /// Creates a detector that reads its models from `resources`.
+    ///
+    /// Panics if the detector can not be created, see [MultiPersonHolisticDetector::try_with_resources].
+    pub fn with_resources(resources: Resources) -> Self {
+        Self::try_with_resources(resources).expect("Failed to create multi person holistic detector")
+    }
+
+    /// Creates a detector that reads its models from `resources`.
+    pub fn try_with_resources(resources: Resources) -> Result<Self, Error> {
+        resources.check(&[
+            POSE_DETECTION_MODEL,
+            pose_landmark_model(ModelComplexity::Full),
+            "mediapipe/modules/holistic_landmark/hand_recrop.tflite",
+            HAND_LANDMARK_FULL_MODEL,
+            FACE_LANDMARK_MODEL,
+        ])?;
+
         let outputs = vec![
             Output {
                 type_: FeatureType::Poses,
@@ -107,53 +272,77 @@ impl MultiPersonHolisticDetector {
             },
         ];
 
-        let graph = Detector::new(include_str!("graphs/multi_person_holistic_tracking_cpu.pbtxt"), outputs);
+        let config = GraphConfig::new(include_str!("graphs/multi_person_holistic_tracking_cpu.pbtxt"));
+        let graph = Detector::try_from_config(&config, &resources, outputs)?;
 
-        Self { graph }
+        Ok(Self { graph, buffer: LandmarkBuffer::new() })
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
     }
 
     /// Processes the input frame, returns landmarks if detected
-    pub fn process(&mut self, input: &mut Mat) -> Vec<HolisticDetection> {
-        let landmarks = self.graph.process(input);
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<HolisticDetection> {
+        self.try_process(input).expect("Failed to process frame")
+    }
 
-        let max_landmarks = landmarks.iter().map(|l| l.len()).reduce(|acc, item| acc.max(item)).unwrap();
+    /// Processes the input frame, returns landmarks if detected
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<HolisticDetection>, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
 
-        let mut detections = vec![];
+    /// Processes the input frame captured at `timestamp`, returns landmarks if detected
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<HolisticDetection> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
 
-        for i in 0..max_landmarks {
-            let mut pose = None;
-            let mut face = None;
-            let mut left_hand = None;
-            let mut right_hand = None;
+    /// Processes the input frame captured at `timestamp`, returns landmarks if detected
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<HolisticDetection>, Error> {
+        // Landmarks are normalized to the frame size, distances are compared in units of the height.
+        let (width, height) = self.graph.frame_size(&input.as_image_view()?);
+        let aspect_ratio = width as f32 / height as f32;
 
-            if landmarks[0].len() > i {
-                let mut p = Pose::default();
-                p.data.copy_from_slice(&landmarks[0][i][..]);
-                pose = Some(p);
-            }
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+        let buffer = &self.buffer;
 
-            if landmarks[1].len() > i {
-                let mut f = FaceMesh::default();
-                f.data.copy_from_slice(&landmarks[1][i][..]);
-                face = Some(f);
-            }
+        let poses = buffer.features::<Pose>(0).collect::<Vec<_>>();
+        let faces = buffer.features::<FaceMesh>(1).collect::<Vec<_>>();
+        let left_hands = buffer.features::<Hand>(2).collect::<Vec<_>>();
+        let right_hands = buffer.features::<Hand>(3).collect::<Vec<_>>();
 
-            if landmarks[2].len() > i {
-                let mut l = Hand::default();
-                l.data.copy_from_slice(&landmarks[2][i][..]);
-                left_hand = Some(l);
-            }
+        let pose_faces = associate(&poses, PoseLandmark::NOSE as usize, faces.iter().map(|f| &f.data[FACE_NOSE_TIP]), aspect_ratio);
+        let pose_left_hands = associate(&poses, PoseLandmark::LEFT_WRIST as usize, left_hands.iter().map(|h| &h.data[HandLandmark::WRIST as usize]), aspect_ratio);
+        let pose_right_hands = associate(&poses, PoseLandmark::RIGHT_WRIST as usize, right_hands.iter().map(|h| &h.data[HandLandmark::WRIST as usize]), aspect_ratio);
 
-            if landmarks[3].len() > i {
-                let mut r = Hand::default();
-                r.data.copy_from_slice(&landmarks[3][i][..]);
-                right_hand = Some(r);
-            }
+        let mut detections = poses
+            .iter()
+            .enumerate()
+            .map(|(i, pose)| HolisticDetection {
+                pose: Some((*pose).clone()),
+                face: pose_faces[i].map(|j| faces[j].clone()),
+                left_hand: pose_left_hands[i].map(|j| left_hands[j].clone()),
+                right_hand: pose_right_hands[i].map(|j| right_hands[j].clone()),
+                segmentation_mask: None,
+            })
+            .collect::<Vec<_>>();
 
-            detections.push(HolisticDetection { pose, face, left_hand, right_hand });
-        }
+        detections.extend(unassigned(&faces, &pose_faces).map(|face| HolisticDetection {
+            face: Some(face.clone()),
+            ..Default::default()
+        }));
+        detections.extend(unassigned(&left_hands, &pose_left_hands).map(|hand| HolisticDetection {
+            left_hand: Some(hand.clone()),
+            ..Default::default()
+        }));
+        detections.extend(unassigned(&right_hands, &pose_right_hands).map(|hand| HolisticDetection {
+            right_hand: Some(hand.clone()),
+            ..Default::default()
+        }));
 
-        detections
+        Ok(detections)
     }
 }
 
@@ -162,3 +351,120 @@ impl Default for MultiPersonHolisticDetector {
         Self::new()
     }
 }
+
+/// Distance between two landmarks in the image plane, in units of the frame height.
+///
+/// Landmarks are normalized to the frame's width and height, `aspect_ratio` (width / height)
+/// scales `x` so both axes have the same unit.
+fn distance(a: &Landmark, b: &Landmark, aspect_ratio: f32) -> f32 {
+    ((a.x - b.x) * aspect_ratio).hypot(a.y - b.y)
+}
+
+/// Distance between the centers of the shoulders and of the hips, a measure of the pose's size.
+fn torso_length(pose: &Pose, aspect_ratio: f32) -> f32 {
+    let center = |a: PoseLandmark, b: PoseLandmark| {
+        let (a, b) = (&pose.data[a as usize], &pose.data[b as usize]);
+        Landmark {
+            x: (a.x + b.x) / 2.0,
+            y: (a.y + b.y) / 2.0,
+            ..Default::default()
+        }
+    };
+    distance(&center(PoseLandmark::LEFT_SHOULDER, PoseLandmark::RIGHT_SHOULDER), &center(PoseLandmark::LEFT_HIP, PoseLandmark::RIGHT_HIP), aspect_ratio)
+}
+
+/// Matches features to poses by the distance of each feature's `anchors` to the pose landmark
+/// `pose_anchor`, closest pairs first. Returns the index of the feature matched to each pose.
+fn associate<'a>(poses: &[&Pose], pose_anchor: usize, anchors: impl Iterator<Item = &'a Landmark>, aspect_ratio: f32) -> Vec<Option<usize>> {
+    let anchors = anchors.collect::<Vec<_>>();
+
+    let mut pairs = vec![];
+    for (i, pose) in poses.iter().enumerate() {
+        let max_distance = MAX_ASSOCIATION_DISTANCE * torso_length(pose, aspect_ratio);
+        for (j, anchor) in anchors.iter().enumerate() {
+            let d = distance(&pose.data[pose_anchor], anchor, aspect_ratio);
+            if d <= max_distance {
+                pairs.push((d, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut matches = vec![None; poses.len()];
+    let mut matched = vec![false; anchors.len()];
+    for (_, i, j) in pairs {
+        if matches[i].is_none() && !matched[j] {
+            matches[i] = Some(j);
+            matched[j] = true;
+        }
+    }
+    matches
+}
+
+/// The features not matched to any pose by [associate].
+fn unassigned<'a, T>(features: &'a [&'a T], matches: &'a [Option<usize>]) -> impl Iterator<Item = &'a T> {
+    features.iter().enumerate().filter(move |(j, _)| !matches.contains(&Some(*j))).map(|(_, f)| *f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A standing pose with its torso centered at `x`, the torso is 0.4 frame heights long.
+    fn pose_at(x: f32) -> Pose {
+        let mut pose = Pose::default();
+        for (landmark, dx, y) in [
+            (PoseLandmark::NOSE, 0.0, 0.2),
+            (PoseLandmark::LEFT_SHOULDER, 0.05, 0.3),
+            (PoseLandmark::RIGHT_SHOULDER, -0.05, 0.3),
+            (PoseLandmark::LEFT_HIP, 0.05, 0.7),
+            (PoseLandmark::RIGHT_HIP, -0.05, 0.7),
+            (PoseLandmark::LEFT_WRIST, 0.1, 0.6),
+            (PoseLandmark::RIGHT_WRIST, -0.1, 0.6),
+        ] {
+            pose.data[landmark as usize] = Landmark { x: x + dx, y, ..Default::default() };
+        }
+        pose
+    }
+
+    fn landmark(x: f32, y: f32) -> Landmark {
+        Landmark { x, y, ..Default::default() }
+    }
+
+    #[test]
+    fn associates_streams_with_different_counts() {
+        let (first, second) = (pose_at(0.25), pose_at(0.75));
+        let poses = [&first, &second];
+
+        // One face for two poses, three hands of which one is nowhere near a pose.
+        let faces = [landmark(0.76, 0.21)];
+        let hands = [landmark(0.9, 0.1), landmark(0.36, 0.6), landmark(0.84, 0.61)];
+
+        let pose_faces = associate(&poses, PoseLandmark::NOSE as usize, faces.iter(), 1.0);
+        assert_eq!(pose_faces, [None, Some(0)]);
+        assert_eq!(unassigned(&faces.iter().collect::<Vec<_>>(), &pose_faces).count(), 0);
+
+        let pose_hands = associate(&poses, PoseLandmark::LEFT_WRIST as usize, hands.iter(), 1.0);
+        assert_eq!(pose_hands, [Some(1), Some(2)]);
+        let hands = hands.iter().collect::<Vec<_>>();
+        let unmatched = unassigned(&hands, &pose_hands).collect::<Vec<_>>();
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].x, 0.9);
+
+        // No poses at all, every feature is unassigned.
+        let pose_hands = associate(&[], PoseLandmark::LEFT_WRIST as usize, hands.iter().copied(), 1.0);
+        assert!(pose_hands.is_empty());
+        assert_eq!(unassigned(&hands, &pose_hands).count(), 3);
+    }
+
+    #[test]
+    fn distances_account_for_the_aspect_ratio() {
+        let pose = pose_at(0.5);
+        // 0.15 frame widths to the side of the nose, within half a torso length on a square frame
+        // but not on a 16:9 one.
+        let faces = [landmark(0.65, 0.2)];
+
+        assert_eq!(associate(&[&pose], PoseLandmark::NOSE as usize, faces.iter(), 1.0), [Some(0)]);
+        assert_eq!(associate(&[&pose], PoseLandmark::NOSE as usize, faces.iter(), 16.0 / 9.0), [None]);
+    }
+}
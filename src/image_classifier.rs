@@ -0,0 +1,132 @@
+//! Image classification utilities.
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+/// Configures an [ImageClassifier].
+#[derive(Debug, Clone)]
+pub struct ImageClassifierBuilder {
+    model_path: PathBuf,
+    top_k: usize,
+    score_threshold: f32,
+    resources: Resources,
+}
+
+impl ImageClassifierBuilder {
+    pub fn new(model_path: impl AsRef<Path>) -> Self {
+        Self {
+            model_path: model_path.as_ref().to_path_buf(),
+            top_k: 5,
+            score_threshold: 0.0,
+            resources: Resources::default(),
+        }
+    }
+
+    /// Sets the number of best scoring categories returned per frame, defaults to 5.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Sets the minimum score of the returned categories, between 0 and 1, defaults to 0.
+    pub fn score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = score_threshold;
+        self
+    }
+
+    /// Sets where the model is read from, defaults to the directory set with
+    /// [set_resource_root_dir]. A relative `model_path` is looked up in it like the bundled
+    /// models, e.g. in the files added with [Resources::with_file].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the classifier can not be created, see [ImageClassifierBuilder::try_build].
+    pub fn build(self) -> ImageClassifier {
+        self.try_build().expect("Failed to create image classifier")
+    }
+
+    pub fn try_build(self) -> Result<ImageClassifier, Error> {
+        let model_path = self.model_path.to_str().ok_or_else(|| Error::InvalidGraphConfig(format!("model path {} is not valid UTF-8", self.model_path.display())))?;
+        self.resources.check(&[model_path])?;
+        let top_k = i32::try_from(self.top_k)
+            .ok()
+            .filter(|&k| k > 0)
+            .ok_or_else(|| Error::InvalidGraphConfig(format!("top_k must be between 1 and {}, got {}", i32::MAX, self.top_k)))?;
+        let score_threshold = FieldValue::confidence("score_threshold", self.score_threshold)?;
+
+        let mut config = GraphConfig::new(include_str!("graphs/image_classification_cpu.pbtxt"));
+        config
+            .set_field("file_name", &[FieldValue::Str(model_path.into())])?
+            .set_field("max_results", &[FieldValue::Int(top_k)])?
+            .set_field("score_threshold", &[score_threshold])?;
+
+        let graph = Detector::try_from_config(
+            &config,
+            &self.resources,
+            vec![Output {
+                type_: FeatureType::Classifications,
+                name: "classifications".into(),
+            }],
+        )?;
+
+        Ok(ImageClassifier { graph, buffer: LandmarkBuffer::new() })
+    }
+}
+
+/// Classifies whole frames with a user supplied TFLite model with metadata.
+pub struct ImageClassifier {
+    graph: Detector,
+    buffer: LandmarkBuffer,
+}
+
+impl ImageClassifier {
+    pub fn new(model_path: impl AsRef<Path>) -> Self {
+        Self::try_new(model_path).expect("Failed to create image classifier")
+    }
+
+    pub fn try_new(model_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::builder(model_path).try_build()
+    }
+
+    /// Starts configuring an image classifier for the model at `model_path`.
+    pub fn builder(model_path: impl AsRef<Path>) -> ImageClassifierBuilder {
+        ImageClassifierBuilder::new(model_path)
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
+    }
+
+    /// Processes the input frame, returns the top categories, best scoring first.
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<Classification> {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns the top categories, best scoring first.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<Classification>, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the top categories, best
+    /// scoring first.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<Classification> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the top categories, best
+    /// scoring first.
+    ///
+    /// Models with several classifier heads only report the first one.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<Classification>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+
+        let heads = self.graph.packet(0)?.and_then(|p| p.data.into_classifications()).unwrap_or_default();
+        let mut categories = heads.into_iter().next().unwrap_or_default();
+        categories.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(categories)
+    }
+}
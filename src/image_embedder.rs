@@ -0,0 +1,187 @@
+//! Image embedding utilities.
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+/// A feature vector of an image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Embedding {
+    /// The model output, normalized if enabled.
+    Float(Vec<f32>),
+    /// Scalar quantized to 8 bits, each value `x` stored as `round(x * 128)` clamped to the `i8`
+    /// range.
+    Quantized(Vec<i8>),
+}
+
+impl Embedding {
+    /// Number of dimensions.
+    pub fn len(&self) -> usize {
+        match self {
+            Embedding::Float(v) => v.len(),
+            Embedding::Quantized(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cosine similarity to `other`, between -1 and 1.
+    ///
+    /// Both embeddings have to be of the same kind and length, e.g. from the same embedder.
+    pub fn cosine_similarity(&self, other: &Embedding) -> Result<f32, Error> {
+        match (self, other) {
+            (Embedding::Float(a), Embedding::Float(b)) => cosine_similarity(a, b),
+            (Embedding::Quantized(a), Embedding::Quantized(b)) => cosine_similarity(a, b),
+            _ => Err(Error::InvalidInput("can not compare a float and a quantized embedding".into())),
+        }
+    }
+}
+
+fn cosine_similarity<T: Copy + Into<f64>>(a: &[T], b: &[T]) -> Result<f32, Error> {
+    if a.len() != b.len() {
+        return Err(Error::InvalidInput(format!("can not compare embeddings of {} and {} dimensions", a.len(), b.len())));
+    }
+
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for (&a, &b) in a.iter().zip(b) {
+        let (a, b): (f64, f64) = (a.into(), b.into());
+        dot += a * b;
+        norm_a += a * a;
+        norm_b += b * b;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Err(Error::InvalidInput("can not compare embeddings with a norm of zero".into()));
+    }
+    Ok((dot / (norm_a.sqrt() * norm_b.sqrt())) as f32)
+}
+
+/// Configures an [ImageEmbedder].
+#[derive(Debug, Clone)]
+pub struct ImageEmbedderBuilder {
+    model_path: PathBuf,
+    l2_normalize: bool,
+    quantize: bool,
+    resources: Resources,
+}
+
+impl ImageEmbedderBuilder {
+    pub fn new(model_path: impl AsRef<Path>) -> Self {
+        Self {
+            model_path: model_path.as_ref().to_path_buf(),
+            l2_normalize: false,
+            quantize: false,
+            resources: Resources::default(),
+        }
+    }
+
+    /// Scales the embeddings to a length of one, defaults to `false`.
+    pub fn l2_normalize(mut self, l2_normalize: bool) -> Self {
+        self.l2_normalize = l2_normalize;
+        self
+    }
+
+    /// Returns [Embedding::Quantized] instead of [Embedding::Float] embeddings, defaults to
+    /// `false`.
+    ///
+    /// Values outside of -1 to 1 are clamped, so this is best combined with
+    /// [ImageEmbedderBuilder::l2_normalize].
+    pub fn quantize(mut self, quantize: bool) -> Self {
+        self.quantize = quantize;
+        self
+    }
+
+    /// Sets where the model is read from, defaults to the directory set with
+    /// [set_resource_root_dir]. A relative `model_path` is looked up in it like the bundled
+    /// models, e.g. in the files added with [Resources::with_file].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the embedder can not be created, see [ImageEmbedderBuilder::try_build].
+    pub fn build(self) -> ImageEmbedder {
+        self.try_build().expect("Failed to create image embedder")
+    }
+
+    pub fn try_build(self) -> Result<ImageEmbedder, Error> {
+        let model_path = self.model_path.to_str().ok_or_else(|| Error::InvalidGraphConfig(format!("model path {} is not valid UTF-8", self.model_path.display())))?;
+        self.resources.check(&[model_path])?;
+
+        let mut config = GraphConfig::new(include_str!("graphs/image_embedding_cpu.pbtxt"));
+        config.set_field("file_name", &[FieldValue::Str(model_path.into())])?.set_field("l2_normalize", &[FieldValue::Bool(self.l2_normalize)])?;
+
+        let graph = Detector::try_from_config(
+            &config,
+            &self.resources,
+            vec![Output {
+                type_: FeatureType::Floats,
+                name: "embeddings".into(),
+            }],
+        )?;
+
+        Ok(ImageEmbedder {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            quantize: self.quantize,
+        })
+    }
+}
+
+/// Computes feature vectors of whole frames with a user supplied TFLite model with metadata,
+/// e.g. to find similar or duplicate frames.
+pub struct ImageEmbedder {
+    graph: Detector,
+    buffer: LandmarkBuffer,
+    quantize: bool,
+}
+
+impl ImageEmbedder {
+    pub fn new(model_path: impl AsRef<Path>) -> Self {
+        Self::try_new(model_path).expect("Failed to create image embedder")
+    }
+
+    pub fn try_new(model_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::builder(model_path).try_build()
+    }
+
+    /// Starts configuring an image embedder for the model at `model_path`.
+    pub fn builder(model_path: impl AsRef<Path>) -> ImageEmbedderBuilder {
+        ImageEmbedderBuilder::new(model_path)
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
+    }
+
+    /// Processes the input frame, returns its embedding.
+    pub fn process(&mut self, input: &impl AsImageView) -> Embedding {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns its embedding.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Embedding, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns its embedding.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Embedding {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns its embedding.
+    ///
+    /// Models with several embedder heads only report the first one.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Embedding, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+
+        let embedding = self.graph.packet(0)?.and_then(|p| p.data.into_floats()).ok_or_else(|| Error::Runtime("graph returned no embedding".into()))?;
+        if self.quantize {
+            Ok(Embedding::Quantized(embedding.iter().map(|v| (v * 128.0).round().clamp(-128.0, 127.0) as i8).collect()))
+        } else {
+            Ok(Embedding::Float(embedding))
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Iris tracking utilities.
+use super::*;
+
+/// Average diameter of the human iris in millimeters, which varies little between people.
+pub const IRIS_DIAMETER_MM: f32 = 11.7;
+
+/// Average radius of the human eyeball in millimeters.
+const EYEBALL_RADIUS_MM: f32 = 12.0;
+
+/// Face mesh landmarks used to locate an eye: iris center, iris contour, outer and inner corner.
+struct EyeLandmarks {
+    center: usize,
+    contour: [usize; 4],
+    corners: [usize; 2],
+}
+
+const RIGHT_EYE: EyeLandmarks = EyeLandmarks {
+    center: 468,
+    contour: [469, 470, 471, 472],
+    corners: [33, 133],
+};
+
+const LEFT_EYE: EyeLandmarks = EyeLandmarks {
+    center: 473,
+    contour: [474, 475, 476, 477],
+    corners: [263, 362],
+};
+
+/// Face mesh landmarks at the top of the forehead and the bottom of the chin.
+const FOREHEAD: usize = 10;
+const CHIN: usize = 152;
+
+/// An eye, seen from the person.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// The iris of an eye.
+#[derive(Debug, Clone, Copy)]
+pub struct Iris {
+    /// Horizontal position of the center in pixels.
+    pub x: f32,
+    /// Vertical position of the center in pixels.
+    pub y: f32,
+    /// Radius in pixels.
+    pub radius: f32,
+    /// Direction the eye looks in, a unit vector with x to the right, y down and z away from the
+    /// camera. Looking into the camera is about `[0, 0, -1]`.
+    pub gaze: [f32; 3],
+}
+
+impl Iris {
+    /// Locates the iris of `eye` in a face mesh detected on a `width` by `height` frame.
+    ///
+    /// Returns `None` if the mesh has no iris landmarks, see
+    /// [face_mesh::FaceMeshDetectorBuilder::refine_landmarks].
+    pub fn from_face_mesh(face: &FaceMesh, eye: Eye, width: u32, height: u32) -> Option<Self> {
+        let landmarks = match eye {
+            Eye::Left => &LEFT_EYE,
+            Eye::Right => &RIGHT_EYE,
+        };
+        let point = |i: usize| {
+            let l = &face.data[i];
+            // Landmark z uses roughly the same scale as x.
+            [l.x * width as f32, l.y * height as f32, l.z * width as f32]
+        };
+
+        if landmarks.contour.iter().chain(&[landmarks.center]).all(|&i| face.data[i].x == 0.0 && face.data[i].y == 0.0) {
+            return None;
+        }
+
+        let center = point(landmarks.center);
+        let radius = landmarks.contour.iter().map(|&i| (point(i)[0] - center[0]).hypot(point(i)[1] - center[1])).sum::<f32>() / landmarks.contour.len() as f32;
+
+        // The face normal, pointing towards the camera.
+        let mut normal = normalize(cross(sub(point(LEFT_EYE.corners[0]), point(RIGHT_EYE.corners[0])), sub(point(CHIN), point(FOREHEAD))));
+        if normal[2] > 0.0 {
+            normal = [-normal[0], -normal[1], -normal[2]];
+        }
+
+        // The eyeball center lies behind the middle of the eye corners, its size is derived from
+        // the iris size.
+        let [outer, inner] = landmarks.corners.map(point);
+        let eyeball_radius = radius * EYEBALL_RADIUS_MM / (IRIS_DIAMETER_MM / 2.0);
+        let eyeball_center = [0, 1, 2].map(|i| (outer[i] + inner[i]) / 2.0 - normal[i] * eyeball_radius);
+
+        Some(Self {
+            x: center[0],
+            y: center[1],
+            radius,
+            gaze: normalize(sub(center, eyeball_center)),
+        })
+    }
+
+    /// Distance of the iris from the camera along its optical axis in millimeters, estimated from
+    /// [IRIS_DIAMETER_MM].
+    ///
+    /// `focal_length` is the focal length of the camera in pixels, see [focal_length].
+    pub fn depth(&self, focal_length: f32) -> f32 {
+        focal_length * IRIS_DIAMETER_MM / (2.0 * self.radius)
+    }
+}
+
+/// Both irises of a face.
+#[derive(Debug, Clone, Copy)]
+pub struct Irises {
+    pub left: Iris,
+    pub right: Iris,
+}
+
+impl Irises {
+    /// Locates both irises in a face mesh detected on a `width` by `height` frame, see
+    /// [Iris::from_face_mesh].
+    pub fn from_face_mesh(face: &FaceMesh, width: u32, height: u32) -> Option<Self> {
+        Some(Self {
+            left: Iris::from_face_mesh(face, Eye::Left, width, height)?,
+            right: Iris::from_face_mesh(face, Eye::Right, width, height)?,
+        })
+    }
+
+    /// Distance of the face from the camera in millimeters, the mean of both [Iris::depth].
+    pub fn depth(&self, focal_length: f32) -> f32 {
+        (self.left.depth(focal_length) + self.right.depth(focal_length)) / 2.0
+    }
+
+    /// Direction the person looks in, the mean of both eyes' gaze.
+    pub fn gaze(&self) -> [f32; 3] {
+        normalize([0, 1, 2].map(|i| self.left.gaze[i] + self.right.gaze[i]))
+    }
+}
+
+/// Focal length in pixels of a camera whose frames are `size` pixels across a field of view of
+/// `fov_degrees`, both horizontal or both vertical.
+pub fn focal_length(fov_degrees: f32, size: u32) -> f32 {
+    size as f32 / 2.0 / (fov_degrees.to_radians() / 2.0).tan()
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length == 0.0 {
+        v
+    } else {
+        [v[0] / length, v[1] / length, v[2] / length]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 640;
+    const HEIGHT: u32 = 480;
+    /// Iris radius of the synthetic face in pixels.
+    const RADIUS: f32 = 8.0;
+
+    fn set(face: &mut FaceMesh, i: usize, x: f32, y: f32) {
+        face.data[i] = Landmark {
+            x: x / WIDTH as f32,
+            y: y / HEIGHT as f32,
+            ..Default::default()
+        };
+    }
+
+    /// A face looking straight into the camera, its eyes centered at x = 256 and 384 and y = 192,
+    /// with the irises shifted `dx` pixels to the right.
+    fn frontal_face(dx: f32) -> FaceMesh {
+        let mut face = FaceMesh::default();
+        set(&mut face, FOREHEAD, 320.0, 96.0);
+        set(&mut face, CHIN, 320.0, 336.0);
+        for (eye, x) in [(&RIGHT_EYE, 256.0), (&LEFT_EYE, 384.0)] {
+            // The outer corners are further from the middle of the face.
+            let outward = if x < 320.0 { -1.0 } else { 1.0 };
+            set(&mut face, eye.corners[0], x + outward * 32.0, 192.0);
+            set(&mut face, eye.corners[1], x - outward * 32.0, 192.0);
+            set(&mut face, eye.center, x + dx, 192.0);
+            for (&i, (cx, cy)) in eye.contour.iter().zip([(1.0, 0.0), (0.0, -1.0), (-1.0, 0.0), (0.0, 1.0)]) {
+                set(&mut face, i, x + dx + cx * RADIUS, 192.0 + cy * RADIUS);
+            }
+        }
+        face
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn irises_are_located_in_pixels() {
+        let irises = Irises::from_face_mesh(&frontal_face(0.0), WIDTH, HEIGHT).unwrap();
+        for (iris, x) in [(irises.right, 256.0), (irises.left, 384.0)] {
+            assert_close(iris.x, x);
+            assert_close(iris.y, 192.0);
+            assert_close(iris.radius, RADIUS);
+        }
+    }
+
+    #[test]
+    fn depth_follows_the_focal_length() {
+        let irises = Irises::from_face_mesh(&frontal_face(0.0), WIDTH, HEIGHT).unwrap();
+        let focal_length = focal_length(90.0, WIDTH);
+        assert_close(focal_length, 320.0);
+        // An iris of 11.7 mm is 16 pixels across at 320 / 16 * 11.7 mm.
+        assert_close(irises.left.depth(focal_length), 234.0);
+        assert_close(irises.depth(focal_length), 234.0);
+        assert_close(irises.depth(2.0 * focal_length), 468.0);
+    }
+
+    #[test]
+    fn frontal_faces_look_into_the_camera() {
+        let irises = Irises::from_face_mesh(&frontal_face(0.0), WIDTH, HEIGHT).unwrap();
+        for gaze in [irises.left.gaze, irises.right.gaze, irises.gaze()] {
+            assert_close(gaze[0], 0.0);
+            assert_close(gaze[1], 0.0);
+            assert_close(gaze[2], -1.0);
+        }
+
+        // Irises moved to the right of the eyes look to the right.
+        let gaze = Irises::from_face_mesh(&frontal_face(6.0), WIDTH, HEIGHT).unwrap().gaze();
+        assert!(gaze[0] > 0.1 && gaze[2] < 0.0, "{:?}", gaze);
+        assert_close(gaze[1], 0.0);
+    }
+
+    #[test]
+    fn unrefined_meshes_have_no_irises() {
+        // Without refined landmarks the iris landmarks are zero padding.
+        let mut face = frontal_face(0.0);
+        for i in 468..478 {
+            face.data[i] = Landmark::default();
+        }
+        assert!(Iris::from_face_mesh(&face, Eye::Left, WIDTH, HEIGHT).is_none());
+        assert!(Iris::from_face_mesh(&face, Eye::Right, WIDTH, HEIGHT).is_none());
+        assert!(Irises::from_face_mesh(&face, WIDTH, HEIGHT).is_none());
+    }
+}
@@ -0,0 +1,158 @@
+//! Flat, reusable landmark storage.
+use std::{convert::TryFrom, iter::FusedIterator, marker::PhantomData, slice::ChunksExact};
+
+use super::{FaceMesh, Hand, Landmark, Pose};
+
+/// A fixed size set of landmarks that can be viewed in place inside a [LandmarkBuffer].
+pub trait Feature {
+    /// Number of landmarks of one feature.
+    const NUM_LANDMARKS: usize;
+
+    /// Views exactly [Feature::NUM_LANDMARKS] landmarks as a feature, `None` for any other length.
+    fn from_landmarks(landmarks: &[Landmark]) -> Option<&Self>;
+}
+
+macro_rules! impl_feature {
+    ($t:ty, $n:expr) => {
+        impl Feature for $t {
+            const NUM_LANDMARKS: usize = $n;
+
+            fn from_landmarks(landmarks: &[Landmark]) -> Option<&Self> {
+                let array = <&[Landmark; $n]>::try_from(landmarks).ok()?;
+                // SAFETY: The type is a `#[repr(transparent)]` wrapper around `[Landmark; $n]`.
+                Some(unsafe { &*(array as *const [Landmark; $n] as *const $t) })
+            }
+        }
+    };
+}
+
+impl_feature!(Pose, 33);
+impl_feature!(Hand, 21);
+impl_feature!(FaceMesh, 478);
+
+#[derive(Debug, Clone, Copy)]
+struct OutputRange {
+    offset: usize,
+    count: usize,
+    num_landmarks: usize,
+}
+
+/// The landmarks of all outputs of a [crate::Detector], stored in one flat buffer.
+///
+/// Each output is a contiguous range of features, each feature a contiguous range of landmarks.
+/// Passing the same buffer to [crate::Detector::process_into] for every frame reuses its
+/// allocation, and the typed views ([Pose], [Hand], [FaceMesh]) borrow from it without copying.
+#[derive(Debug, Clone, Default)]
+pub struct LandmarkBuffer {
+    landmarks: Vec<Landmark>,
+    outputs: Vec<OutputRange>,
+}
+
+impl LandmarkBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes all landmarks, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.landmarks.clear();
+        self.outputs.clear();
+    }
+
+    /// Appends the landmarks of the next output. `landmarks` holds `count` features back to back.
+    pub(crate) fn push_output(&mut self, landmarks: &[Landmark], count: usize, num_landmarks: usize) {
+        debug_assert_eq!(landmarks.len(), count * num_landmarks);
+        self.outputs.push(OutputRange {
+            offset: self.landmarks.len(),
+            count,
+            num_landmarks,
+        });
+        self.landmarks.extend_from_slice(landmarks);
+    }
+
+    /// Number of outputs stored.
+    pub fn num_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Number of features detected for `output`.
+    ///
+    /// Panics if `output` is out of range.
+    pub fn count(&self, output: usize) -> usize {
+        self.outputs[output].count
+    }
+
+    /// All landmarks of `output`, the features back to back.
+    ///
+    /// Panics if `output` is out of range.
+    pub fn output(&self, output: usize) -> &[Landmark] {
+        let range = self.outputs[output];
+        &self.landmarks[range.offset..range.offset + range.count * range.num_landmarks]
+    }
+
+    /// The landmarks of each feature of `output`.
+    ///
+    /// Panics if `output` is out of range.
+    pub fn landmarks(&self, output: usize) -> ChunksExact<'_, Landmark> {
+        let num_landmarks = self.outputs[output].num_landmarks.max(1);
+        self.output(output).chunks_exact(num_landmarks)
+    }
+
+    /// Typed views of the features of `output`.
+    ///
+    /// Panics if `output` is out of range or doesn't hold features of type `T`.
+    pub fn features<T: Feature>(&self, output: usize) -> Features<'_, T> {
+        let range = self.outputs[output];
+        assert!(
+            range.count == 0 || range.num_landmarks == T::NUM_LANDMARKS,
+            "output {} holds features of {} landmarks, not {}",
+            output,
+            range.num_landmarks,
+            T::NUM_LANDMARKS
+        );
+        Features {
+            chunks: self.output(output).chunks_exact(T::NUM_LANDMARKS),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Typed view of the first feature of `output`, if any was detected.
+    ///
+    /// Panics if `output` is out of range or doesn't hold features of type `T`.
+    pub fn first<T: Feature>(&self, output: usize) -> Option<&T> {
+        self.features(output).next()
+    }
+
+    /// Copies the landmarks into one `Vec` per output and feature.
+    pub fn to_nested(&self) -> Vec<Vec<Vec<Landmark>>> {
+        (0..self.num_outputs()).map(|i| self.landmarks(i).map(|l| l.to_vec()).collect()).collect()
+    }
+}
+
+/// Iterator over typed views into a [LandmarkBuffer].
+pub struct Features<'a, T> {
+    chunks: ChunksExact<'a, Landmark>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: Feature> Iterator for Features<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chunks.next().and_then(T::from_landmarks)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<T: Feature> DoubleEndedIterator for Features<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.chunks.next_back().and_then(T::from_landmarks)
+    }
+}
+
+impl<T: Feature> ExactSizeIterator for Features<'_, T> {}
+
+impl<T: Feature> FusedIterator for Features<'_, T> {}
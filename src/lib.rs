@@ -8,25 +8,54 @@
 //! This library brings Google's Mediapipe to Rust.
 
 // LINKING
-#[link(name = "mediagraph")]
+// Unit tests run against `bindings::mock` instead.
+#[cfg_attr(not(test), link(name = "mediagraph"))]
 extern "C" {}
 
 use opencv::prelude::*;
-use std::{ffi::CString, path::Path};
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    time::{Duration, Instant},
+};
 
 mod bindings;
+mod config;
+mod error;
+pub mod face_detection;
 pub mod face_mesh;
+mod frame;
+pub mod gesture;
+mod graph;
 pub mod hands;
 pub mod holistic;
+pub mod image_classifier;
+pub mod image_embedder;
+pub mod iris;
+mod landmarks;
+pub mod object_detection;
+mod packet;
+mod pool;
 pub mod pose;
+mod resources;
 pub mod segmentation;
 
 use bindings::*;
+use config::{CalculatorOption, FieldValue, GraphConfig, SidePacket};
+pub use error::Error;
+use frame::FrameConverter;
+pub use frame::{AsImageView, ImageView, PixelFormat};
+pub use graph::{Graph, GraphBuilder};
+pub use landmarks::{Feature, Features, LandmarkBuffer};
+pub use packet::{BoundingBox, Classification, Detection, Keypoint, Matrix, Packet, PacketData, PacketType, Rect};
+pub use pool::DetectorPool;
+pub use resources::{set_resource_root_dir, try_set_resource_root_dir, Resources};
 
 type mFeatureType = mediagraph_FeatureType;
 type mOutput = mediagraph_Output;
 
-/// The type of visual feature made up of landmarks.
+/// The type of visual feature made up of landmarks, or of the per-feature data that comes with
+/// them.
 #[derive(Debug, Clone, Copy)]
 pub enum FeatureType {
     Face,
@@ -35,6 +64,30 @@ pub enum FeatureType {
     Hands,
     Pose,
     Poses,
+    /// A pose in world coordinates.
+    PoseWorld,
+    /// Poses in world coordinates.
+    PosesWorld,
+    /// Hands in world coordinates.
+    HandsWorld,
+    /// A classification list per feature, e.g. the handedness of each hand. It carries no
+    /// landmarks, read it with [Detector::packet].
+    Classifications,
+    /// An image, e.g. a rendered frame or a segmentation mask. It carries no landmarks, read it
+    /// with [Detector::packet].
+    Image,
+    /// Detections with bounding boxes and keypoints. They carry no landmarks, read them with
+    /// [Detector::packet].
+    Detections,
+    /// Rotated rectangles, e.g. the region of interest each feature was tracked in. They carry no
+    /// landmarks, read them with [Detector::packet].
+    Rects,
+    /// Transformation matrices, e.g. the head pose of each face. They carry no landmarks, read
+    /// them with [Detector::packet].
+    Matrices,
+    /// Float vectors, e.g. image embeddings. They carry no landmarks, read them with
+    /// [Detector::packet].
+    Floats,
 }
 
 impl FeatureType {
@@ -46,6 +99,15 @@ impl FeatureType {
             FeatureType::Hands => 21,
             FeatureType::Pose => 33,
             FeatureType::Poses => 33,
+            FeatureType::PoseWorld => 33,
+            FeatureType::PosesWorld => 33,
+            FeatureType::HandsWorld => 21,
+            FeatureType::Classifications => 0,
+            FeatureType::Image => 0,
+            FeatureType::Detections => 0,
+            FeatureType::Rects => 0,
+            FeatureType::Matrices => 0,
+            FeatureType::Floats => 0,
         }
     }
 }
@@ -59,16 +121,62 @@ impl Into<mFeatureType> for FeatureType {
             FeatureType::Hands => mediagraph_FeatureType_HANDS,
             FeatureType::Pose => mediagraph_FeatureType_POSE,
             FeatureType::Poses => mediagraph_FeatureType_POSES,
+            FeatureType::PoseWorld => mediagraph_FeatureType_POSE_WORLD,
+            FeatureType::PosesWorld => mediagraph_FeatureType_POSES_WORLD,
+            FeatureType::HandsWorld => mediagraph_FeatureType_HANDS_WORLD,
+            FeatureType::Classifications => mediagraph_FeatureType_CLASSIFICATIONS,
+            FeatureType::Image => mediagraph_FeatureType_IMAGE,
+            FeatureType::Detections => mediagraph_FeatureType_DETECTIONS,
+            FeatureType::Rects => mediagraph_FeatureType_RECTS,
+            FeatureType::Matrices => mediagraph_FeatureType_MATRICES,
+            FeatureType::Floats => mediagraph_FeatureType_FLOATS,
         }
     }
 }
 
-pub fn set_resource_root_dir(path: &Path) {
-    unsafe {
-        let str = path.to_str().expect("Expected resource path to be valid UTF-8");
-        let cstr = CString::new(str).expect("Could not convert path to a c compatible string");
+/// How the frames passed to a detector relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunningMode {
+    /// Unrelated images. Every frame runs the detection model, no landmarks are carried over from
+    /// one frame to the next.
+    Image,
+    /// The frames of a video, in order. Features are tracked from frame to frame, the frames have
+    /// to be passed with their timestamps (`process_at`).
+    Video,
+    /// The frames of a live feed. Features are tracked from frame to frame, frames passed without
+    /// a timestamp are stamped with the time elapsed since the detector was created.
+    #[default]
+    LiveStream,
+}
+
+impl RunningMode {
+    /// Whether the landmarks of the previous frame are used to find the features in the next.
+    pub(crate) fn tracks(self) -> bool {
+        self != RunningMode::Image
+    }
+
+    /// The timestamp of a frame passed to `detector` without one.
+    pub(crate) fn timestamp(self, detector: &Detector) -> Result<Duration, Error> {
+        match self {
+            RunningMode::Video => Err(Error::InvalidTimestamp("video frames have to be passed with their timestamps, use process_at".into())),
+            _ => Ok(detector.next_timestamp()),
+        }
+    }
+}
+
+/// Size of the landmark model of a detector, trading accuracy for latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModelComplexity {
+    Lite,
+    #[default]
+    Full,
+    Heavy,
+}
 
-        bindings::mediagraph_set_resource_root_dir(cstr.as_ptr());
+impl ModelComplexity {
+    /// The `model_complexity` side packet selecting the model.
+    pub(crate) fn side_packet(self) -> SidePacket {
+        SidePacket::Int(self as i32)
     }
 }
 
@@ -79,15 +187,21 @@ pub struct Output {
     name: String,
 }
 
-impl Into<mOutput> for Output {
-    fn into(self) -> mOutput {
-        let name = CString::new(self.name).expect("CString::new failed").into_raw();
-
-        mOutput { type_: self.type_.into(), name }
+impl Output {
+    /// Builds the C++ descriptor. It borrows `name`, which has to outlive the descriptor.
+    fn to_raw(&self, name: &CString) -> mOutput {
+        mOutput {
+            type_: self.type_.into(),
+            name: name.as_ptr() as *mut std::os::raw::c_char,
+        }
     }
 }
 
 /// The mediagraph landmark struct from C++.
+///
+/// Image landmarks are normalized to the frame size, `z` uses roughly the scale of `x`. World
+/// landmarks are in meters, with the origin at the center of the hips for poses and at the
+/// geometric center of the hand for hands.
 pub type Landmark = mediagraph_Landmark;
 
 impl Default for Landmark {
@@ -105,6 +219,7 @@ impl Default for Landmark {
 /// Represents a detected pose, as 33 landmarks.
 /// Landmark names are in [pose::PoseLandmark].
 #[derive(Clone, Debug)]
+#[repr(transparent)]
 pub struct Pose {
     pub data: [Landmark; 33],
 }
@@ -118,12 +233,14 @@ impl Default for Pose {
 /// Represents a detected hand, as 21 landmarks.
 /// Landmark names are in [hands::HandLandmark]
 #[derive(Clone, Debug, Default)]
+#[repr(transparent)]
 pub struct Hand {
     pub data: [Landmark; 21],
 }
 
 /// Represents a detected face mesh, as 478 landmarks.
 #[derive(Clone, Debug)]
+#[repr(transparent)]
 pub struct FaceMesh {
     pub data: [Landmark; 478],
 }
@@ -138,96 +255,470 @@ impl Default for FaceMesh {
 pub struct Detector {
     graph: *mut mediagraph_Detector,
     outputs: Vec<Output>,
+    // The C++ output descriptors and the names they point to, kept alive as long as the graph.
+    raw_outputs: Vec<mOutput>,
+    output_names: Vec<CString>,
+    // Number of detected features per output, written by the library.
+    num_features: Vec<u8>,
+    converter: FrameConverter,
+    clock: PacketClock,
 }
 
 impl Detector {
     /// Creates a new Mediagraph with the given config.
+    ///
+    /// Panics if the graph can not be created, see [Detector::try_new].
     pub fn new(graph_config: &str, output_config: Vec<Output>) -> Self {
-        assert!(output_config.len() > 0, "must specify at least one output feature");
-        let graph_config = CString::new(graph_config).expect("CString::new failed");
-
-        let outputs = output_config.iter().map(|f| f.clone().into()).collect::<Vec<mOutput>>();
+        Self::try_new(graph_config, output_config).expect("Failed to create detector")
+    }
 
-        let graph: *mut mediagraph_Detector = unsafe { mediagraph_Detector::Create(graph_config.as_ptr(), outputs.as_ptr(), outputs.len() as u8) };
+    /// Creates a new Mediagraph with the given config.
+    pub fn try_new(graph_config: &str, output_config: Vec<Output>) -> Result<Self, Error> {
+        Self::try_create(graph_config, &[], &Resources::default(), output_config)
+    }
 
-        Self { graph, outputs: output_config }
+    /// Creates a new Mediagraph from an edited config, including its subgraph calculator options,
+    /// reading the model files from `resources`.
+    pub(crate) fn try_from_config(config: &GraphConfig, resources: &Resources, output_config: Vec<Output>) -> Result<Self, Error> {
+        Self::try_create(&config.to_text(), config.calculator_options(), resources, output_config)
     }
 
-    /// Processes the input frame, returns a slice of landmarks if any are detected.
-    pub fn process(&mut self, input: &mut Mat) -> Vec<Vec<Vec<Landmark>>> {
-        let data = input;
-        let mut num_features = vec![0; self.outputs.len()];
+    fn try_create(graph_config: &str, options: &[CalculatorOption], resources: &Resources, output_config: Vec<Output>) -> Result<Self, Error> {
+        if output_config.is_empty() {
+            return Err(Error::InvalidGraphConfig("must specify at least one output feature".into()));
+        }
+        if output_config.len() > u8::MAX as usize {
+            return Err(Error::InvalidGraphConfig(format!("at most {} output features are supported", u8::MAX)));
+        }
+        let graph_config = CString::new(graph_config).map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
 
-        let results = unsafe {
-            let nf_data = num_features.as_mut_slice();
-            mediagraph_Detector_Process(self.graph as *mut std::ffi::c_void, data.data_mut(), data.cols(), data.rows(), nf_data.as_mut_ptr())
+        let output_names = output_config
+            .iter()
+            .map(|o| CString::new(o.name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+        let raw_outputs = output_config.iter().zip(&output_names).map(|(o, name)| o.to_raw(name)).collect::<Vec<mOutput>>();
+
+        if options.len() > u8::MAX as usize {
+            return Err(Error::InvalidGraphConfig(format!("at most {} calculator options are supported", u8::MAX)));
+        }
+        // The option strings only have to live until the graph is created.
+        let option_strings = options
+            .iter()
+            .map(|o| Ok([CString::new(o.node.as_str())?, CString::new(o.field.as_str())?, CString::new(o.value.to_string())?]))
+            .collect::<Result<Vec<_>, std::ffi::NulError>>()
+            .map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+        let raw_options = option_strings
+            .iter()
+            .map(|[node, field, value]| mediagraph_CalculatorOption {
+                node: node.as_ptr(),
+                field: field.as_ptr(),
+                value: value.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        let raw_resources = resources.to_raw()?;
+
+        let graph: *mut mediagraph_Detector = if raw_options.is_empty() && resources.is_global() {
+            unsafe { mediagraph_Detector_Create(graph_config.as_ptr(), raw_outputs.as_ptr(), raw_outputs.len() as u8) }
+        } else {
+            let resources = raw_resources.as_raw();
+            unsafe { mediagraph_Detector_CreateWithOptions(graph_config.as_ptr(), raw_outputs.as_ptr(), raw_outputs.len() as u8, raw_options.as_ptr(), raw_options.len() as u8, &resources) }
         };
 
-        let mut landmarks = vec![];
+        if graph.is_null() {
+            return Err(Error::from_last_status(Error::InvalidGraphConfig).unwrap_or_else(|| Error::InvalidGraphConfig("graph could not be created".into())));
+        }
+
+        Ok(Self {
+            graph,
+            num_features: vec![0; output_config.len()],
+            outputs: output_config,
+            raw_outputs,
+            output_names,
+            converter: FrameConverter::default(),
+            clock: PacketClock::new(),
+        })
+    }
+
+    /// Sets the pixel format of the frames passed to [Detector::process], defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.converter.format = format;
+    }
+
+    /// The pixel format of the frames passed to [Detector::process].
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.converter.format
+    }
+
+    /// Processes the input frame, returns the landmarks of each output.
+    ///
+    /// Panics if the frame can not be processed, see [Detector::try_process].
+    pub fn process(&mut self, input: &impl AsImageView) -> LandmarkBuffer {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns the landmarks of each output.
+    ///
+    /// The frame is timestamped with the time elapsed since the detector was created.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<LandmarkBuffer, Error> {
+        self.try_process_at(input, self.next_timestamp())
+    }
+
+    /// The timestamp [Detector::process] uses for the next frame.
+    pub(crate) fn next_timestamp(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// Width and height in pixels of `input` as the graph sees it.
+    pub(crate) fn frame_size(&self, input: &ImageView) -> (i32, i32) {
+        self.converter.frame_size(input)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the landmarks of each output.
+    ///
+    /// Panics if the frame can not be processed, see [Detector::try_process_at].
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> LandmarkBuffer {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the landmarks of each output.
+    ///
+    /// Timestamps must be strictly increasing (with microsecond resolution), e.g. the position of
+    /// the frame within a recorded video.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<LandmarkBuffer, Error> {
+        let mut buffer = LandmarkBuffer::new();
+        self.try_process_at_into(input, timestamp, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Processes the input frame, replaces the contents of `buffer` with the landmarks of each output.
+    ///
+    /// Panics if the frame can not be processed, see [Detector::try_process_into].
+    pub fn process_into(&mut self, input: &impl AsImageView, buffer: &mut LandmarkBuffer) {
+        self.try_process_into(input, buffer).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, replaces the contents of `buffer` with the landmarks of each output.
+    pub fn try_process_into(&mut self, input: &impl AsImageView, buffer: &mut LandmarkBuffer) -> Result<(), Error> {
+        self.try_process_at_into(input, self.next_timestamp(), buffer)
+    }
+
+    /// Processes the input frame captured at `timestamp`, replaces the contents of `buffer` with
+    /// the landmarks of each output.
+    ///
+    /// Panics if the frame can not be processed, see [Detector::try_process_at_into].
+    pub fn process_at_into(&mut self, input: &impl AsImageView, timestamp: Duration, buffer: &mut LandmarkBuffer) {
+        self.try_process_at_into(input, timestamp, buffer).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, replaces the contents of `buffer` with
+    /// the landmarks of each output.
+    ///
+    /// The buffer keeps its allocation, so reusing it for every frame avoids allocating. It is
+    /// left empty if processing fails.
+    pub fn try_process_at_into(&mut self, input: &impl AsImageView, timestamp: Duration, buffer: &mut LandmarkBuffer) -> Result<(), Error> {
+        buffer.clear();
+
+        let view = input.as_image_view()?;
+        let frame = self.converter.convert(&view)?;
+        // Advanced last, a frame that fails to convert doesn't use up its timestamp.
+        let timestamp_us = self.clock.advance(timestamp)?;
+        let num_features = &mut self.num_features;
+
+        // The library only reads the frame data, it is copied into the graph's input packet.
+        let results = unsafe { mediagraph_Detector_Process(self.graph, frame.data.as_ptr() as *mut u8, frame.width, frame.height, timestamp_us, num_features.as_mut_ptr()) };
+
+        if let Some(e) = Error::from_last_status(Error::Runtime) {
+            return Err(e);
+        }
+        if results.is_null() && num_features.iter().any(|&count| count > 0) {
+            return Err(Error::Runtime("graph returned no landmark data".into()));
+        }
+
+        // The landmarks returned by the library are only valid until the next call, copy them
+        // into the buffer in one go per output.
         let mut data_index = 0;
+        for (output, &count) in self.outputs.iter().zip(num_features.iter()) {
+            let num_landmarks = output.type_.num_landmarks();
+            let len = count as usize * num_landmarks;
+            let landmarks = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(results.add(data_index), len) } };
+            buffer.push_output(landmarks, count as usize, num_landmarks);
+            data_index += len;
+        }
 
-        for (i, &count) in num_features.iter().enumerate() {
-            let mut fl = vec![];
-            if count == 0 {
-                landmarks.push(fl);
-                continue;
-            }
+        Ok(())
+    }
 
-            let num_landmarks = self.outputs[i].type_.num_landmarks();
+    /// The packet the last processed frame produced on `output`, `None` if there was none.
+    ///
+    /// Only outputs without landmarks, like [FeatureType::Classifications], are read this way.
+    pub fn packet(&mut self, output: usize) -> Result<Option<Packet>, Error> {
+        match self.outputs.get(output) {
+            Some(o) if o.type_.num_landmarks() == 0 => {}
+            Some(o) => return Err(Error::InvalidInput(format!("output {} holds {:?} landmarks, not a packet", output, o.type_))),
+            None => return Err(Error::InvalidInput(format!("no output {}", output))),
+        }
 
-            for _ in 0..count {
-                let l = unsafe { std::slice::from_raw_parts(results.add(data_index), num_landmarks) };
-                data_index += num_landmarks;
-                fl.push(l.to_vec());
-            }
+        let mut raw: mediagraph_Packet = unsafe { std::mem::zeroed() };
+        let available = unsafe { mediagraph_Detector_GetPacket(self.graph, output as u8, &mut raw) };
 
-            landmarks.push(fl);
+        if let Some(e) = Error::from_last_status(Error::Runtime) {
+            return Err(e);
+        }
+        if !available {
+            return Ok(None);
         }
 
-        landmarks
+        Packet::from_raw(&raw).map(Some)
     }
 }
 
 impl Drop for Detector {
     fn drop(&mut self) {
+        // Fields are dropped after this, so the graph is gone before the output names it may
+        // reference are freed.
         unsafe {
-            mediagraph_Detector_Detector_destructor(self.graph);
+            mediagraph_Detector_Destroy(self.graph);
         }
     }
 }
 
+// SAFETY: The C++ detector owns its `CalculatorGraph` and output pollers. MediaPipe graphs run
+// their calculators on an internal executor and accept input packets and polls from any thread, so
+// the detector can be moved to another thread. The result buffers returned by `Process` are not
+// synchronized though, which is why every call takes `&mut self` and `Detector` is not `Sync`.
+unsafe impl Send for Detector {}
+
 /// Effect calculator which interacts with the C++ library.
 pub struct Effect {
     graph: *mut mediagraph_Effect,
+    // Kept alive as long as the graph, which may reference it.
+    output_node: CString,
+    converter: FrameConverter,
+    clock: PacketClock,
 }
 
 impl Effect {
     /// Creates a new Mediagraph with the given config.
+    ///
+    /// Panics if the graph can not be created, see [Effect::try_new].
     pub fn new(graph_config: &str, output_node: &str) -> Self {
-        let graph_config = CString::new(graph_config).expect("CString::new failed");
-        let output_node = CString::new(output_node).expect("CString::new failed");
+        Self::try_new(graph_config, output_node).expect("Failed to create effect")
+    }
+
+    /// Creates a new Mediagraph with the given config.
+    pub fn try_new(graph_config: &str, output_node: &str) -> Result<Self, Error> {
+        let graph_config = CString::new(graph_config).map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+        let output_node = CString::new(output_node).map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+
+        let graph: *mut mediagraph_Effect = unsafe { mediagraph_Effect_Create(graph_config.as_ptr(), output_node.as_ptr()) };
+
+        if graph.is_null() {
+            return Err(Error::from_last_status(Error::InvalidGraphConfig).unwrap_or_else(|| Error::InvalidGraphConfig("graph could not be created".into())));
+        }
+
+        Ok(Self {
+            graph,
+            output_node,
+            converter: FrameConverter::default(),
+            clock: PacketClock::new(),
+        })
+    }
+
+    /// Sets the pixel format of the frames passed to [Effect::process], defaults to [PixelFormat::Rgb].
+    ///
+    /// The output frame is always RGB.
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.converter.format = format;
+    }
+
+    /// The pixel format of the frames passed to [Effect::process].
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.converter.format
+    }
+
+    /// Processes the input frame, returns the rendered output frame.
+    ///
+    /// Panics if the frame can not be processed, see [Effect::try_process].
+    pub fn process(&mut self, input: &impl AsImageView) -> Mat {
+        self.try_process(input).expect("Failed to process frame")
+    }
 
-        let graph: *mut mediagraph_Effect = unsafe { mediagraph_Effect::Create(graph_config.as_ptr(), output_node.as_ptr()) };
+    /// Processes the input frame, returns the rendered output frame.
+    ///
+    /// The frame is timestamped with the time elapsed since the effect was created.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Mat, Error> {
+        self.try_process_at(input, self.next_timestamp())
+    }
 
-        Self { graph }
+    /// The timestamp [Effect::process] uses for the next frame.
+    pub(crate) fn next_timestamp(&self) -> Duration {
+        self.clock.now()
     }
 
-    /// Processes the input frame, returns a slice of landmarks if any are detected.
-    pub fn process(&mut self, input: &mut Mat) -> Mat {
-        let data = input;
-        let cols = data.cols();
-        let rows = data.rows();
-        let typ = data.typ();
-        let out_data = unsafe { mediagraph_Effect_Process(self.graph as *mut std::ffi::c_void, data.data_mut(), cols, rows) };
-        //unsafe { Mat::new_rows_cols_with_data(rows, cols, typ, out_data as *mut std::ffi::c_void, opencv::core::Mat_AUTO_STEP).unwrap() }
-        Mat::default()
+    /// Processes the input frame captured at `timestamp`, returns the rendered output frame.
+    ///
+    /// Panics if the frame can not be processed, see [Effect::try_process_at].
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Mat {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the rendered output frame.
+    ///
+    /// Timestamps must be strictly increasing (with microsecond resolution). An empty `Mat` is
+    /// returned if the graph did not produce an output for this frame.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Mat, Error> {
+        let view = input.as_image_view()?;
+        let frame = self.converter.convert(&view)?;
+        let timestamp_us = self.clock.advance(timestamp)?;
+        // The library only reads the frame data, it is copied into the graph's input packet.
+        let output = unsafe { mediagraph_Effect_Process(self.graph, frame.data.as_ptr() as *mut u8, frame.width, frame.height, timestamp_us) };
+
+        if let Some(e) = Error::from_last_status(Error::Runtime) {
+            return Err(e);
+        }
+
+        image_to_mat(&output)
     }
 }
 
 impl Drop for Effect {
     fn drop(&mut self) {
         unsafe {
-            mediagraph_Effect_Effect_destructor(self.graph);
+            mediagraph_Effect_Destroy(self.graph);
         }
     }
 }
+
+// SAFETY: Same as for `Detector`, the graph may be driven from any thread but the output frame
+// buffer is only valid until the next `Process` call on the same effect.
+unsafe impl Send for Effect {}
+
+/// Copies an image returned by the C++ library into an owned `Mat`, empty if there is no image.
+///
+/// 8-bit images have 1, 3 or 4 channels, float images (segmentation masks) are single channel.
+/// The pixel buffer is owned by the C++ graph and only stays valid until the next call into it (or
+/// until the graph is dropped), so it is copied right away.
+fn image_to_mat(image: &mediagraph_Image) -> Result<Mat, Error> {
+    if image.data.is_null() {
+        return Ok(Mat::default());
+    }
+
+    let typ = match (image.is_float, image.channels) {
+        (false, 1) => opencv::core::CV_8UC1,
+        (false, 3) => opencv::core::CV_8UC3,
+        (false, 4) => opencv::core::CV_8UC4,
+        (true, 1) => opencv::core::CV_32FC1,
+        (_, n) => return Err(Error::Runtime(format!("unsupported number of output channels: {}", n))),
+    };
+    let view = unsafe { Mat::new_rows_cols_with_data(image.height, image.width, typ, image.data as *mut std::ffi::c_void, image.width_step as usize) }?;
+
+    Ok(view.try_clone()?)
+}
+
+/// Hands out the strictly increasing packet timestamps a graph requires.
+struct PacketClock {
+    start: Instant,
+    last_us: Option<i64>,
+}
+
+impl PacketClock {
+    fn new() -> Self {
+        Self { start: Instant::now(), last_us: None }
+    }
+
+    /// A timestamp for a frame without an explicit one: the time since the clock was created, but
+    /// always after the last timestamp handed out.
+    fn now(&self) -> Duration {
+        let elapsed = self.start.elapsed();
+        match self.last_us {
+            Some(last) if elapsed.as_micros() as i64 <= last => Duration::from_micros(last as u64 + 1),
+            _ => elapsed,
+        }
+    }
+
+    /// Returns `timestamp` in microseconds if it comes after the last one.
+    fn advance(&mut self, timestamp: Duration) -> Result<i64, Error> {
+        let timestamp_us = i64::try_from(timestamp.as_micros()).map_err(|_| Error::InvalidTimestamp(format!("{:?} is out of range", timestamp)))?;
+        if let Some(last) = self.last_us {
+            if timestamp_us <= last {
+                return Err(Error::InvalidTimestamp(format!("{:?} is not after the previous timestamp {:?}", timestamp, Duration::from_micros(last as u64))));
+            }
+        }
+        self.last_us = Some(timestamp_us);
+        Ok(timestamp_us)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effect_output_has_the_input_size() {
+        let (width, height) = (7, 5);
+        let input = (0..width * height * 3).map(|i| i as u8).collect::<Vec<_>>();
+        let view = ImageView::new(&input, width, height, width as usize * 3, PixelFormat::Rgb).unwrap();
+
+        let mut effect = Effect::new("", "output_video");
+        let output = effect.process(&view);
+
+        assert_eq!((output.cols(), output.rows()), (width as i32, height as i32));
+        assert_eq!(output.typ(), opencv::core::CV_8UC3);
+        // The rendered rows are padded, the copy has to drop the padding.
+        assert_eq!(output.data_bytes().unwrap(), &input[..]);
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let max_nv12_height = i32::MAX as u32 / 3 * 2;
+        assert!(ImageView::new(&[0; 4], 2, max_nv12_height + 1, 2, PixelFormat::Nv12).is_err());
+        // The buffer rows of tall NV12 frames still fit in an i32, the buffer is just too small.
+        for height in [i32::MAX as u32 / 2, max_nv12_height] {
+            assert!(matches!(ImageView::new(&[0; 4], 2, height, 2, PixelFormat::Nv12), Err(Error::InvalidInput(_))));
+        }
+        assert!(matches!(ImageView::new(&[0; 4], 1, 3, usize::MAX / 2, PixelFormat::Gray), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn dropped_detectors_free_their_graphs() {
+        let outputs = || {
+            vec![
+                Output {
+                    type_: FeatureType::Hands,
+                    name: "hand_landmarks".into(),
+                },
+                Output {
+                    type_: FeatureType::Pose,
+                    name: "pose_landmarks".into(),
+                },
+            ]
+        };
+        let input = vec![0; 4 * 4 * 3];
+        let view = ImageView::new(&input, 4, 4, 4 * 3, PixelFormat::Rgb).unwrap();
+
+        for _ in 0..1000 {
+            let mut detector = Detector::new("", outputs());
+            detector.process(&view);
+        }
+        assert_eq!(bindings::mock::live_graphs(), 0);
+
+        let detectors = (0..100).map(|_| Detector::new("", outputs())).collect::<Vec<_>>();
+        assert_eq!(bindings::mock::live_graphs(), 100);
+        drop(detectors);
+        assert_eq!(bindings::mock::live_graphs(), 0);
+    }
+
+    #[test]
+    fn user_models_are_looked_up_in_the_resources() {
+        let resources = Resources::default().with_file("models/model.tflite", &b"model"[..]);
+
+        assert!(image_classifier::ImageClassifier::builder("models/model.tflite").resources(resources.clone()).try_build().is_ok());
+        assert!(image_embedder::ImageEmbedder::builder("models/model.tflite").resources(resources.clone()).try_build().is_ok());
+        assert!(object_detection::ObjectDetector::builder("models/model.tflite").resources(resources.clone()).try_build().is_ok());
+
+        let missing = object_detection::ObjectDetector::builder("models/missing.tflite").resources(resources).try_build();
+        assert!(matches!(missing, Err(Error::MissingResource(_))));
+    }
+}
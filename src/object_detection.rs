@@ -0,0 +1,188 @@
+//! Object detection utilities.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::*;
+
+/// Configures an [ObjectDetector].
+#[derive(Debug, Clone)]
+pub struct ObjectDetectorBuilder {
+    model_path: PathBuf,
+    label_map: Option<PathBuf>,
+    score_threshold: f32,
+    max_results: Option<usize>,
+    category_allowlist: Vec<String>,
+    category_denylist: Vec<String>,
+    resources: Resources,
+}
+
+impl ObjectDetectorBuilder {
+    pub fn new(model_path: impl AsRef<Path>) -> Self {
+        Self {
+            model_path: model_path.as_ref().to_path_buf(),
+            label_map: None,
+            score_threshold: 0.0,
+            max_results: None,
+            category_allowlist: vec![],
+            category_denylist: vec![],
+            resources: Resources::default(),
+        }
+    }
+
+    /// Reads the labels from a file with one label per line, the line index being the label id.
+    /// Defaults to the labels in the model metadata.
+    pub fn label_map(mut self, label_map: impl AsRef<Path>) -> Self {
+        self.label_map = Some(label_map.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the minimum score of the returned detections, between 0 and 1, defaults to 0.
+    pub fn score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = score_threshold;
+        self
+    }
+
+    /// Sets the maximum number of detections returned per frame, at least 1. The best scoring ones
+    /// are kept. Defaults to no limit.
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.max_results = Some(max_results);
+        self
+    }
+
+    /// Only returns detections with one of these labels, defaults to all labels.
+    pub fn category_allowlist<S: Into<String>>(mut self, labels: impl IntoIterator<Item = S>) -> Self {
+        self.category_allowlist = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Drops detections with one of these labels, defaults to none.
+    pub fn category_denylist<S: Into<String>>(mut self, labels: impl IntoIterator<Item = S>) -> Self {
+        self.category_denylist = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets where the model is read from, defaults to the directory set with
+    /// [set_resource_root_dir]. A relative `model_path` is looked up in it like the bundled
+    /// models, e.g. in the files added with [Resources::with_file].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [ObjectDetectorBuilder::try_build].
+    pub fn build(self) -> ObjectDetector {
+        self.try_build().expect("Failed to create object detector")
+    }
+
+    pub fn try_build(self) -> Result<ObjectDetector, Error> {
+        if !self.category_allowlist.is_empty() && !self.category_denylist.is_empty() {
+            return Err(Error::InvalidGraphConfig("category_allowlist and category_denylist are mutually exclusive".into()));
+        }
+        if self.max_results == Some(0) {
+            return Err(Error::InvalidGraphConfig("max_results must be at least 1, got 0".into()));
+        }
+        let score_threshold = FieldValue::confidence("score_threshold", self.score_threshold)?;
+
+        let model_path = self.model_path.to_str().ok_or_else(|| Error::InvalidGraphConfig(format!("model path {} is not valid UTF-8", self.model_path.display())))?;
+        self.resources.check(&[model_path])?;
+        let labels = match &self.label_map {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|e| Error::MissingResource(format!("{}: {}", path.display(), e)))?
+                .lines()
+                .map(|l| l.trim().to_string())
+                .collect(),
+            None => vec![],
+        };
+
+        let mut config = GraphConfig::new(include_str!("graphs/object_detection_cpu.pbtxt"));
+        config.set_field("file_name", &[FieldValue::Str(model_path.into())])?.set_field("score_threshold", &[score_threshold])?;
+
+        let graph = Detector::try_from_config(
+            &config,
+            &self.resources,
+            vec![Output {
+                type_: FeatureType::Detections,
+                name: "detections".into(),
+            }],
+        )?;
+
+        Ok(ObjectDetector {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            labels,
+            max_results: self.max_results,
+            category_allowlist: self.category_allowlist,
+            category_denylist: self.category_denylist,
+        })
+    }
+}
+
+/// Detects objects with a user supplied TFLite model, e.g. EfficientDet or SSD with TFLite
+/// metadata.
+pub struct ObjectDetector {
+    graph: Detector,
+    buffer: LandmarkBuffer,
+    labels: Vec<String>,
+    max_results: Option<usize>,
+    category_allowlist: Vec<String>,
+    category_denylist: Vec<String>,
+}
+
+impl ObjectDetector {
+    pub fn new(model_path: impl AsRef<Path>) -> Self {
+        Self::try_new(model_path).expect("Failed to create object detector")
+    }
+
+    pub fn try_new(model_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::builder(model_path).try_build()
+    }
+
+    /// Starts configuring an object detector for the model at `model_path`.
+    pub fn builder(model_path: impl AsRef<Path>) -> ObjectDetectorBuilder {
+        ObjectDetectorBuilder::new(model_path)
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
+    }
+
+    /// Processes the input frame, returns the detected objects, best scoring first.
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<Detection> {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns the detected objects, best scoring first.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<Detection>, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the detected objects, best
+    /// scoring first.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<Detection> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the detected objects, best
+    /// scoring first.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<Detection>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+
+        let mut detections = self.graph.packet(0)?.and_then(|p| p.data.into_detections()).unwrap_or_default();
+        for detection in &mut detections {
+            if let Some(label) = usize::try_from(detection.label_id).ok().and_then(|i| self.labels.get(i)) {
+                detection.label = label.clone();
+            }
+        }
+
+        detections.retain(|d| (self.category_allowlist.is_empty() || self.category_allowlist.contains(&d.label)) && !self.category_denylist.contains(&d.label));
+        detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+        if let Some(max_results) = self.max_results {
+            detections.truncate(max_results);
+        }
+        Ok(detections)
+    }
+}
@@ -0,0 +1,451 @@
+//! Typed packets exchanged with a [crate::Graph].
+use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    slice,
+};
+
+use super::*;
+
+/// The type of the packets carried by a graph stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    /// `ImageFrame` packets.
+    Image,
+    /// `NormalizedLandmarkList` or `std::vector<NormalizedLandmarkList>` packets.
+    Landmarks,
+    /// `Detection` or `std::vector<Detection>` packets.
+    Detections,
+    /// `ClassificationList` or `std::vector<ClassificationList>` packets, or the classifier heads of
+    /// `ClassificationResult` packets.
+    Classifications,
+    /// `NormalizedRect` or `std::vector<NormalizedRect>` packets.
+    Rects,
+    /// `std::vector<float>` packets, or the float embedding of the first head of `EmbeddingResult`
+    /// packets.
+    Floats,
+    /// `Matrix` packets.
+    Matrix,
+    /// `std::vector<MatrixData>` packets, or the pose transform matrices of
+    /// `std::vector<FaceGeometry>` packets. All matrices have the same size.
+    Matrices,
+}
+
+impl PacketType {
+    pub(crate) fn to_raw(self) -> mediagraph_PacketType {
+        match self {
+            PacketType::Image => mediagraph_PacketType_IMAGE,
+            PacketType::Landmarks => mediagraph_PacketType_LANDMARKS,
+            PacketType::Detections => mediagraph_PacketType_DETECTIONS,
+            PacketType::Classifications => mediagraph_PacketType_CLASSIFICATIONS,
+            PacketType::Rects => mediagraph_PacketType_RECTS,
+            PacketType::Floats => mediagraph_PacketType_FLOATS,
+            PacketType::Matrix => mediagraph_PacketType_MATRIX,
+            PacketType::Matrices => mediagraph_PacketType_MATRICES,
+        }
+    }
+}
+
+/// A bounding box in normalized image coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoundingBox {
+    pub xmin: f32,
+    pub ymin: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A detection keypoint in normalized image coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Keypoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A detected object, with the best scoring label.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Detection {
+    pub score: f32,
+    pub label_id: i32,
+    /// Empty if the graph doesn't map label ids to names.
+    pub label: String,
+    pub bbox: BoundingBox,
+    pub keypoints: Vec<Keypoint>,
+}
+
+/// One category of a classification result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Classification {
+    pub index: i32,
+    pub score: f32,
+    pub label: String,
+    pub display_name: String,
+}
+
+/// A rotated rectangle in normalized image coordinates, `rotation` is in radians.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub x_center: f32,
+    pub y_center: f32,
+    pub width: f32,
+    pub height: f32,
+    pub rotation: f32,
+}
+
+/// A float matrix, stored column-major like MediaPipe's `Matrix`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<f32>,
+}
+
+impl Matrix {
+    /// The element at `row` and `col`.
+    ///
+    /// Panics if the position is out of range.
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        assert!(row < self.rows && col < self.cols, "({}, {}) is outside of a {}x{} matrix", row, col, self.rows, self.cols);
+        self.data[col * self.rows + row]
+    }
+}
+
+/// The contents of a packet.
+#[derive(Debug)]
+pub enum PacketData {
+    /// An 8-bit RGB, RGBA or grayscale image, or a float (`CV_32FC1`) mask. Images sent to a
+    /// graph are converted from the graph's pixel format like detector input.
+    Image(Mat),
+    /// One landmark list per detected feature.
+    Landmarks(Vec<Vec<Landmark>>),
+    Detections(Vec<Detection>),
+    /// One classification list per classified object.
+    Classifications(Vec<Vec<Classification>>),
+    Rects(Vec<Rect>),
+    Floats(Vec<f32>),
+    Matrix(Matrix),
+    Matrices(Vec<Matrix>),
+}
+
+impl PacketData {
+    /// The type of the packet.
+    pub fn packet_type(&self) -> PacketType {
+        match self {
+            PacketData::Image(_) => PacketType::Image,
+            PacketData::Landmarks(_) => PacketType::Landmarks,
+            PacketData::Detections(_) => PacketType::Detections,
+            PacketData::Classifications(_) => PacketType::Classifications,
+            PacketData::Rects(_) => PacketType::Rects,
+            PacketData::Floats(_) => PacketType::Floats,
+            PacketData::Matrix(_) => PacketType::Matrix,
+            PacketData::Matrices(_) => PacketType::Matrices,
+        }
+    }
+    /// The image, `None` for other packets.
+    pub fn into_image(self) -> Option<Mat> {
+        match self {
+            PacketData::Image(image) => Some(image),
+            _ => None,
+        }
+    }
+
+    /// The detections, `None` for other packets.
+    pub fn into_detections(self) -> Option<Vec<Detection>> {
+        match self {
+            PacketData::Detections(detections) => Some(detections),
+            _ => None,
+        }
+    }
+
+    /// The rects, `None` for other packets.
+    pub fn into_rects(self) -> Option<Vec<Rect>> {
+        match self {
+            PacketData::Rects(rects) => Some(rects),
+            _ => None,
+        }
+    }
+
+    /// The floats, `None` for other packets.
+    pub fn into_floats(self) -> Option<Vec<f32>> {
+        match self {
+            PacketData::Floats(floats) => Some(floats),
+            _ => None,
+        }
+    }
+
+    /// The matrices, `None` for other packets.
+    pub fn into_matrices(self) -> Option<Vec<Matrix>> {
+        match self {
+            PacketData::Matrices(matrices) => Some(matrices),
+            _ => None,
+        }
+    }
+
+    /// The classification lists, `None` for other packets.
+    pub fn into_classifications(self) -> Option<Vec<Vec<Classification>>> {
+        match self {
+            PacketData::Classifications(lists) => Some(lists),
+            _ => None,
+        }
+    }
+}
+
+/// A packet received from a graph output stream.
+#[derive(Debug)]
+pub struct Packet {
+    pub timestamp: Duration,
+    pub data: PacketData,
+}
+
+/// Copies a string owned by the C++ library, null becomes empty.
+fn string_from_raw(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+    }
+}
+
+/// Views an array owned by the C++ library, null pointers and empty arrays are allowed.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` valid elements if `len` is positive.
+unsafe fn slice_from_raw<'a, T>(ptr: *const T, len: i32) -> &'a [T] {
+    if ptr.is_null() || len <= 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len as usize)
+    }
+}
+
+/// Splits `items` into consecutive lists with the given sizes.
+fn split_lists<T, U>(items: &[T], sizes: &[i32], convert: impl Fn(&T) -> U) -> Result<Vec<Vec<U>>, Error> {
+    let mut lists = Vec::with_capacity(sizes.len());
+    let mut offset = 0;
+    for &size in sizes {
+        let end = offset + size.max(0) as usize;
+        let list = items.get(offset..end).ok_or_else(|| Error::Runtime("packet list sizes exceed the packet data".into()))?;
+        lists.push(list.iter().map(&convert).collect());
+        offset = end;
+    }
+    Ok(lists)
+}
+
+impl Packet {
+    /// Copies a packet returned by the C++ library, whose buffers are only valid until the next
+    /// poll of the same stream.
+    pub(crate) fn from_raw(raw: &mediagraph_Packet) -> Result<Self, Error> {
+        let timestamp = Duration::from_micros(raw.timestamp_us.max(0) as u64);
+        let sizes = unsafe { slice_from_raw(raw.list_sizes, raw.num_lists) };
+        let total = sizes.iter().map(|&s| s.max(0)).sum::<i32>();
+
+        let data = match raw.type_ {
+            mediagraph_PacketType_IMAGE => PacketData::Image(image_to_mat(&raw.image)?),
+            mediagraph_PacketType_LANDMARKS => {
+                let landmarks = unsafe { slice_from_raw(raw.landmarks, total) };
+                PacketData::Landmarks(split_lists(landmarks, sizes, |l| *l)?)
+            }
+            mediagraph_PacketType_DETECTIONS => {
+                let detections = unsafe { slice_from_raw(raw.detections, raw.num_detections) };
+                PacketData::Detections(
+                    detections
+                        .iter()
+                        .map(|d| Detection {
+                            score: d.score,
+                            label_id: d.label_id,
+                            label: string_from_raw(d.label),
+                            bbox: BoundingBox {
+                                xmin: d.box_.xmin,
+                                ymin: d.box_.ymin,
+                                width: d.box_.width,
+                                height: d.box_.height,
+                            },
+                            keypoints: unsafe { slice_from_raw(d.keypoints, d.num_keypoints) }.iter().map(|k| Keypoint { x: k.x, y: k.y }).collect(),
+                        })
+                        .collect(),
+                )
+            }
+            mediagraph_PacketType_CLASSIFICATIONS => {
+                let classifications = unsafe { slice_from_raw(raw.classifications, total) };
+                PacketData::Classifications(split_lists(classifications, sizes, |c| Classification {
+                    index: c.index,
+                    score: c.score,
+                    label: string_from_raw(c.label),
+                    display_name: string_from_raw(c.display_name),
+                })?)
+            }
+            mediagraph_PacketType_RECTS => {
+                let rects = unsafe { slice_from_raw(raw.rects, raw.num_rects) };
+                PacketData::Rects(
+                    rects
+                        .iter()
+                        .map(|r| Rect {
+                            x_center: r.x_center,
+                            y_center: r.y_center,
+                            width: r.width,
+                            height: r.height,
+                            rotation: r.rotation,
+                        })
+                        .collect(),
+                )
+            }
+            mediagraph_PacketType_FLOATS => PacketData::Floats(unsafe { slice_from_raw(raw.floats, raw.num_floats) }.to_vec()),
+            mediagraph_PacketType_MATRIX => {
+                let rows = raw.rows.max(0) as usize;
+                let cols = raw.cols.max(0) as usize;
+                let data = unsafe { slice_from_raw(raw.floats, raw.num_floats) }.to_vec();
+                if data.len() != rows * cols {
+                    return Err(Error::Runtime(format!("matrix packet of {}x{} has {} elements", rows, cols, data.len())));
+                }
+                PacketData::Matrix(Matrix { rows, cols, data })
+            }
+            mediagraph_PacketType_MATRICES => {
+                let rows = raw.rows.max(0) as usize;
+                let cols = raw.cols.max(0) as usize;
+                let count = raw.num_lists.max(0) as usize;
+                let data = unsafe { slice_from_raw(raw.floats, raw.num_floats) };
+                if data.len() != count * rows * cols {
+                    return Err(Error::Runtime(format!("packet of {} matrices of {}x{} has {} elements", count, rows, cols, data.len())));
+                }
+                // `chunks` panics on a chunk size of zero.
+                let matrices = if rows * cols == 0 {
+                    (0..count).map(|_| Matrix { rows, cols, data: vec![] }).collect()
+                } else {
+                    data.chunks(rows * cols).map(|m| Matrix { rows, cols, data: m.to_vec() }).collect()
+                };
+                PacketData::Matrices(matrices)
+            }
+            t => return Err(Error::Runtime(format!("unknown packet type {}", t))),
+        };
+
+        Ok(Self { timestamp, data })
+    }
+}
+
+/// The buffers behind a packet laid out for the C++ library.
+///
+/// The raw packet points into these buffers and into the [PacketData] it was built from, both
+/// have to outlive it.
+#[derive(Default)]
+pub(crate) struct RawPacket {
+    list_sizes: Vec<i32>,
+    landmarks: Vec<Landmark>,
+    strings: Vec<CString>,
+    classifications: Vec<mediagraph_Classification>,
+    keypoints: Vec<mediagraph_Keypoint>,
+    detections: Vec<mediagraph_Detection>,
+    rects: Vec<mediagraph_Rect>,
+    floats: Vec<f32>,
+}
+
+impl RawPacket {
+    fn c_string(&mut self, s: &str) -> Result<*const c_char, Error> {
+        let s = CString::new(s).map_err(|e| Error::InvalidInput(e.to_string()))?;
+        // The heap buffer of a `CString` doesn't move when the `CString` itself is moved.
+        let ptr = s.as_ptr();
+        self.strings.push(s);
+        Ok(ptr)
+    }
+
+    /// Lays out a non-image packet, the caller sets its timestamp. Images are converted by the
+    /// graph's frame converter instead.
+    pub(crate) fn as_raw(&mut self, data: &PacketData) -> Result<mediagraph_Packet, Error> {
+        // All pointers and counts start out null and zero.
+        let mut raw: mediagraph_Packet = unsafe { std::mem::zeroed() };
+        raw.type_ = data.packet_type().to_raw();
+
+        match data {
+            PacketData::Image(_) => return Err(Error::InvalidInput("image packets are sent with Graph::send_image".into())),
+            PacketData::Landmarks(lists) => {
+                self.list_sizes = lists.iter().map(|l| l.len() as i32).collect();
+                self.landmarks = lists.iter().flatten().copied().collect();
+                raw.landmarks = self.landmarks.as_ptr();
+                raw.list_sizes = self.list_sizes.as_ptr();
+                raw.num_lists = self.list_sizes.len() as i32;
+            }
+            PacketData::Detections(detections) => {
+                // Keypoints are collected first, so their buffer doesn't reallocate while
+                // detections point into it.
+                self.keypoints = detections.iter().flat_map(|d| d.keypoints.iter()).map(|k| mediagraph_Keypoint { x: k.x, y: k.y }).collect();
+                let mut offset = 0;
+                for d in detections {
+                    let label = self.c_string(&d.label)?;
+                    self.detections.push(mediagraph_Detection {
+                        score: d.score,
+                        label_id: d.label_id,
+                        label,
+                        box_: mediagraph_BoundingBox {
+                            xmin: d.bbox.xmin,
+                            ymin: d.bbox.ymin,
+                            width: d.bbox.width,
+                            height: d.bbox.height,
+                        },
+                        keypoints: unsafe { self.keypoints.as_ptr().add(offset) },
+                        num_keypoints: d.keypoints.len() as i32,
+                    });
+                    offset += d.keypoints.len();
+                }
+                raw.detections = self.detections.as_ptr();
+                raw.num_detections = self.detections.len() as i32;
+            }
+            PacketData::Classifications(lists) => {
+                self.list_sizes = lists.iter().map(|l| l.len() as i32).collect();
+                for c in lists.iter().flatten() {
+                    let label = self.c_string(&c.label)?;
+                    let display_name = self.c_string(&c.display_name)?;
+                    self.classifications.push(mediagraph_Classification {
+                        index: c.index,
+                        score: c.score,
+                        label,
+                        display_name,
+                    });
+                }
+                raw.classifications = self.classifications.as_ptr();
+                raw.list_sizes = self.list_sizes.as_ptr();
+                raw.num_lists = self.list_sizes.len() as i32;
+            }
+            PacketData::Rects(rects) => {
+                self.rects = rects
+                    .iter()
+                    .map(|r| mediagraph_Rect {
+                        x_center: r.x_center,
+                        y_center: r.y_center,
+                        width: r.width,
+                        height: r.height,
+                        rotation: r.rotation,
+                    })
+                    .collect();
+                raw.rects = self.rects.as_ptr();
+                raw.num_rects = self.rects.len() as i32;
+            }
+            PacketData::Floats(floats) => {
+                raw.floats = floats.as_ptr();
+                raw.num_floats = floats.len() as i32;
+            }
+            PacketData::Matrix(matrix) => {
+                if matrix.data.len() != matrix.rows * matrix.cols {
+                    return Err(Error::InvalidInput(format!("matrix of {}x{} has {} elements", matrix.rows, matrix.cols, matrix.data.len())));
+                }
+                raw.floats = matrix.data.as_ptr();
+                raw.num_floats = matrix.data.len() as i32;
+                raw.rows = matrix.rows as i32;
+                raw.cols = matrix.cols as i32;
+            }
+            PacketData::Matrices(matrices) => {
+                let (rows, cols) = matrices.first().map_or((0, 0), |m| (m.rows, m.cols));
+                if let Some(m) = matrices.iter().find(|m| m.rows != rows || m.cols != cols || m.data.len() != rows * cols) {
+                    return Err(Error::InvalidInput(format!("matrix of {}x{} with {} elements in a packet of {}x{} matrices", m.rows, m.cols, m.data.len(), rows, cols)));
+                }
+                self.floats = matrices.iter().flat_map(|m| m.data.iter()).copied().collect();
+                raw.floats = self.floats.as_ptr();
+                raw.num_floats = self.floats.len() as i32;
+                raw.num_lists = matrices.len() as i32;
+                raw.rows = rows as i32;
+                raw.cols = cols as i32;
+            }
+        }
+
+        Ok(raw)
+    }
+}
@@ -0,0 +1,114 @@
+//! Running several graphs on worker threads.
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use super::Error;
+
+type Job<D> = Box<dyn FnOnce(&mut D) + Send>;
+
+struct Worker<D> {
+    sender: mpsc::Sender<Job<D>>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// A fixed number of detectors (or any other solution type), each owned by its own worker thread.
+///
+/// Work is sent to the workers over channels, so several camera feeds can be processed at once.
+/// The tracking graphs keep state between frames, so all frames of one feed should go to the same
+/// worker with [DetectorPool::execute_on].
+pub struct DetectorPool<D> {
+    workers: Vec<Worker<D>>,
+    next: AtomicUsize,
+}
+
+impl<D: Send + 'static> DetectorPool<D> {
+    /// Creates a pool of `size` detectors built by `factory`.
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(size: usize, factory: impl Fn() -> D) -> Self {
+        Self::try_new(size, || Ok(factory())).expect("Failed to create detector pool")
+    }
+
+    /// Creates a pool of `size` detectors built by `factory`, stops at the first detector that
+    /// can not be created. Fails if `size` is zero.
+    pub fn try_new(size: usize, factory: impl Fn() -> Result<D, Error>) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::InvalidGraphConfig("a detector pool needs at least one worker".into()));
+        }
+
+        let mut workers = Vec::with_capacity(size);
+        for i in 0..size {
+            let mut detector = factory()?;
+            let (sender, receiver) = mpsc::channel::<Job<D>>();
+            let handle = thread::Builder::new()
+                .name(format!("mediapipe-worker-{}", i))
+                .spawn(move || {
+                    for job in receiver {
+                        job(&mut detector);
+                    }
+                })
+                .map_err(|e| Error::Runtime(format!("could not spawn worker thread: {}", e)))?;
+            workers.push(Worker { sender, handle });
+        }
+
+        Ok(Self { workers, next: AtomicUsize::new(0) })
+    }
+
+    /// Number of workers in the pool.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Always `false`, a pool has at least one worker.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Queues `job` on the workers in round-robin order.
+    ///
+    /// The returned receiver yields the result once the job ran. It is disconnected without a
+    /// result if the job panicked.
+    pub fn execute<R, F>(&self, job: F) -> mpsc::Receiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut D) -> R + Send + 'static,
+    {
+        let worker = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.execute_on(worker, job)
+    }
+
+    /// Queues `job` on the worker with the given index.
+    ///
+    /// Panics if `worker` is out of range. The returned receiver yields the result once the job
+    /// ran. It is disconnected without a result if the job (or an earlier one on the same worker)
+    /// panicked.
+    pub fn execute_on<R, F>(&self, worker: usize, job: F) -> mpsc::Receiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut D) -> R + Send + 'static,
+    {
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job: Job<D> = Box::new(move |detector| {
+            // The caller may have dropped the receiver, the result is discarded then.
+            let _ = result_sender.send(job(detector));
+        });
+        // Sending only fails if the worker died from a panic, which disconnects the result channel.
+        let _ = self.workers[worker].sender.send(job);
+        result_receiver
+    }
+}
+
+impl<D> Drop for DetectorPool<D> {
+    fn drop(&mut self) {
+        for worker in self.workers.drain(..) {
+            // Closing the channel ends the worker loop after the queued jobs ran.
+            drop(worker.sender);
+            let _ = worker.handle.join();
+        }
+    }
+}
@@ -40,36 +40,324 @@ pub enum PoseLandmark {
     RIGHT_FOOT_INDEX = 32,
 }
 
+/// A detected pose.
+#[derive(Clone, Debug)]
+pub struct PoseDetection {
+    /// Landmarks in normalized image coordinates.
+    pub landmarks: Pose,
+    /// Landmarks in meters, if enabled with `world_landmarks` on the detector builder.
+    pub world_landmarks: Option<Pose>,
+    /// The person detection the pose was found from. Only set on frames the detection model ran
+    /// on, the pose is tracked from the previous landmarks otherwise. Detections of multiple poses
+    /// are matched to the pose whose face they box.
+    pub detection: Option<Detection>,
+    /// The rotated region of interest derived from the landmarks, where the pose is looked for in
+    /// the next frame. ROIs of multiple poses are matched to the pose they enclose.
+    pub roi: Option<Rect>,
+    /// Per-pixel confidence that the pixel belongs to the person (`CV_32FC1`, 0 to 1), if
+    /// enabled with [PoseDetectorBuilder::enable_segmentation]. The multi-person graph has no
+    /// segmentation output, so it is always `None` for the poses of a [MultiPoseDetector].
+    pub segmentation_mask: Option<Mat>,
+}
+
+/// The mean position of `landmarks` in normalized image coordinates.
+fn centroid(landmarks: &[Landmark]) -> (f32, f32) {
+    let n = landmarks.len() as f32;
+    (landmarks.iter().map(|l| l.x).sum::<f32>() / n, landmarks.iter().map(|l| l.y).sum::<f32>() / n)
+}
+
+/// An image region reported per pose, in no particular order.
+trait PoseRegion {
+    /// How far the pose is from the center of the region, `None` if the region doesn't cover it.
+    fn distance(&self, pose: &Pose) -> Option<f32>;
+}
+
+impl PoseRegion for Detection {
+    /// Pose detections box the face, which has to contain the centroid of the face landmarks.
+    fn distance(&self, pose: &Pose) -> Option<f32> {
+        let (x, y) = centroid(&pose.data[PoseLandmark::NOSE as usize..=PoseLandmark::MOUTH_RIGHT as usize]);
+        let b = &self.bbox;
+        let inside = (b.xmin..=b.xmin + b.width).contains(&x) && (b.ymin..=b.ymin + b.height).contains(&y);
+        inside.then(|| (x - b.xmin - b.width / 2.0).hypot(y - b.ymin - b.height / 2.0))
+    }
+}
+
+impl PoseRegion for Rect {
+    /// ROIs enclose the whole pose, the centroid of its landmarks has to be within the circle
+    /// around the rotated rect.
+    fn distance(&self, pose: &Pose) -> Option<f32> {
+        let (x, y) = centroid(&pose.data);
+        let distance = (x - self.x_center).hypot(y - self.y_center);
+        (distance <= self.width.hypot(self.height) / 2.0).then_some(distance)
+    }
+}
+
+/// Pairs the per-pose `regions` of a packet, which come in no particular order, with the poses
+/// they cover, closest pairs first. Poses without a region covering them get `None`.
+fn per_pose<T: PoseRegion>(regions: Option<Vec<T>>, poses: &[&Pose]) -> Vec<Option<T>> {
+    let regions = regions.unwrap_or_default();
+
+    let mut pairs = vec![];
+    for (i, pose) in poses.iter().enumerate() {
+        for (j, region) in regions.iter().enumerate() {
+            if let Some(d) = region.distance(pose) {
+                pairs.push((d, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut regions = regions.into_iter().map(Some).collect::<Vec<_>>();
+
+    let mut matches = poses.iter().map(|_| None).collect::<Vec<_>>();
+    for (_, i, j) in pairs {
+        if matches[i].is_none() && regions[j].is_some() {
+            matches[i] = regions[j].take();
+        }
+    }
+    matches
+}
+
+/// Outputs shared by the pose graphs, in this order after the landmarks.
+fn pose_outputs(detections: &str, rois: &str) -> [Output; 2] {
+    [
+        Output {
+            type_: FeatureType::Detections,
+            name: detections.into(),
+        },
+        Output {
+            type_: FeatureType::Rects,
+            name: rois.into(),
+        },
+    ]
+}
+
+pub(crate) const POSE_DETECTION_MODEL: &str = "mediapipe/modules/pose_detection/pose_detection.tflite";
+
+/// The pose landmark model file of `model_complexity`.
+pub(crate) fn pose_landmark_model(model_complexity: ModelComplexity) -> &'static str {
+    match model_complexity {
+        ModelComplexity::Lite => "mediapipe/modules/pose_landmark/pose_landmark_lite.tflite",
+        ModelComplexity::Full => "mediapipe/modules/pose_landmark/pose_landmark_full.tflite",
+        ModelComplexity::Heavy => "mediapipe/modules/pose_landmark/pose_landmark_heavy.tflite",
+    }
+}
+
+/// Expanded names of the calculators applying the confidence thresholds of the pose subgraphs.
+pub(crate) const POSE_DETECTION_NODE: &str = "posedetectioncpu__TensorsToDetectionsCalculator";
+pub(crate) const POSE_TRACKING_NODE: &str = "poselandmarkbyroicpu__tensorstoposelandmarksandsegmentation__ThresholdingCalculator";
+
+/// Configures a [PoseDetector].
+#[derive(Debug, Clone)]
+pub struct PoseDetectorBuilder {
+    enable_segmentation: bool,
+    world_landmarks: bool,
+    running_mode: RunningMode,
+    model_complexity: ModelComplexity,
+    smooth_landmarks: bool,
+    min_detection_confidence: f32,
+    min_tracking_confidence: f32,
+    resources: Resources,
+}
+
+impl Default for PoseDetectorBuilder {
+    fn default() -> Self {
+        Self {
+            enable_segmentation: false,
+            world_landmarks: false,
+            running_mode: RunningMode::default(),
+            model_complexity: ModelComplexity::default(),
+            smooth_landmarks: true,
+            min_detection_confidence: 0.5,
+            min_tracking_confidence: 0.5,
+            resources: Resources::default(),
+        }
+    }
+}
+
+impl PoseDetectorBuilder {
+    /// Also returns the landmarks in world coordinates, defaults to `false`.
+    pub fn world_landmarks(mut self, world_landmarks: bool) -> Self {
+        self.world_landmarks = world_landmarks;
+        self
+    }
+
+    /// Enables the segmentation model and returns its mask, defaults to `false`.
+    pub fn enable_segmentation(mut self, enable_segmentation: bool) -> Self {
+        self.enable_segmentation = enable_segmentation;
+        self
+    }
+
+    /// Sets how the frames passed to the detector relate to each other, defaults to
+    /// [RunningMode::LiveStream].
+    pub fn running_mode(mut self, running_mode: RunningMode) -> Self {
+        self.running_mode = running_mode;
+        self
+    }
+
+    /// Sets the size of the landmark model, defaults to [ModelComplexity::Full].
+    pub fn model_complexity(mut self, model_complexity: ModelComplexity) -> Self {
+        self.model_complexity = model_complexity;
+        self
+    }
+
+    /// Filters the landmarks across frames to reduce jitter, defaults to `true`. Has no effect
+    /// with [RunningMode::Image].
+    pub fn smooth_landmarks(mut self, smooth_landmarks: bool) -> Self {
+        self.smooth_landmarks = smooth_landmarks;
+        self
+    }
+
+    /// Sets the minimum score of a pose detection for it to be tracked, defaults to 0.5.
+    pub fn min_detection_confidence(mut self, min_detection_confidence: f32) -> Self {
+        self.min_detection_confidence = min_detection_confidence;
+        self
+    }
+
+    /// Sets the minimum landmark score for the pose to be tracked into the next frame, defaults to
+    /// 0.5. Below it the detection model runs again.
+    pub fn min_tracking_confidence(mut self, min_tracking_confidence: f32) -> Self {
+        self.min_tracking_confidence = min_tracking_confidence;
+        self
+    }
+
+    /// Sets where the model files are read from, defaults to the directory set with
+    /// [set_resource_root_dir].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [PoseDetectorBuilder::try_build].
+    pub fn build(self) -> PoseDetector {
+        self.try_build().expect("Failed to create pose detector")
+    }
+
+    pub fn try_build(self) -> Result<PoseDetector, Error> {
+        self.resources.check(&[POSE_DETECTION_MODEL, pose_landmark_model(self.model_complexity)])?;
+
+        let mut config = GraphConfig::new(include_str!("graphs/pose_tracking_cpu.pbtxt"));
+        config
+            .set_side_packet("enable_segmentation", SidePacket::Bool(self.enable_segmentation))?
+            .set_side_packet("model_complexity", self.model_complexity.side_packet())?
+            .set_side_packet("use_prev_landmarks", SidePacket::Bool(self.running_mode.tracks()))?
+            .set_side_packet("smooth_landmarks", SidePacket::Bool(self.smooth_landmarks && self.running_mode.tracks()))?
+            .set_calculator_option(POSE_DETECTION_NODE, "min_score_thresh", FieldValue::confidence("min_detection_confidence", self.min_detection_confidence)?)
+            .set_calculator_option(POSE_TRACKING_NODE, "threshold", FieldValue::confidence("min_tracking_confidence", self.min_tracking_confidence)?);
+
+        let mut outputs = vec![Output {
+            type_: FeatureType::Pose,
+            name: "pose_landmarks".into(),
+        }];
+        outputs.extend(pose_outputs("pose_detection", "roi_from_landmarks"));
+        let mut world_output = None;
+        if self.world_landmarks {
+            world_output = Some(outputs.len());
+            outputs.push(Output {
+                type_: FeatureType::PoseWorld,
+                name: "pose_world_landmarks".into(),
+            });
+        }
+        let mut mask_output = None;
+        if self.enable_segmentation {
+            mask_output = Some(outputs.len());
+            outputs.push(Output {
+                type_: FeatureType::Image,
+                name: "segmentation_mask".into(),
+            });
+        }
+
+        let graph = Detector::try_from_config(&config, &self.resources, outputs)?;
+
+        Ok(PoseDetector {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            world_output,
+            mask_output,
+            running_mode: self.running_mode,
+        })
+    }
+}
+
 pub struct PoseDetector {
     graph: Detector,
+    buffer: LandmarkBuffer,
+    world_output: Option<usize>,
+    mask_output: Option<usize>,
+    running_mode: RunningMode,
 }
 
 impl PoseDetector {
     pub fn new() -> Self {
-        let graph = Detector::new(
-            include_str!("graphs/pose_tracking_cpu.pbtxt"),
-            vec![Output {
-                type_: FeatureType::Pose,
-                name: "pose_landmarks".into(),
-            }],
-        );
+        Self::try_new().expect("Failed to create pose detector")
+    }
 
-        Self { graph }
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a pose detector.
+    pub fn builder() -> PoseDetectorBuilder {
+        PoseDetectorBuilder::default()
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
     }
 
     /// Processes the input frame, returns a pose if detected.
-    pub fn process(&mut self, input: &mut Mat) -> Option<Pose> {
-        let result = self.graph.process(input);
+    pub fn process(&mut self, input: &impl AsImageView) -> Option<PoseDetection> {
+        self.try_process(input).expect("Failed to process frame")
+    }
 
-        if result[0].is_empty() {
-            return None;
-        }
+    /// Processes the input frame, returns a pose if detected.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Option<PoseDetection>, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at(input, timestamp)
+    }
 
-        let landmarks = &result[0][0];
+    /// Processes the input frame captured at `timestamp`, returns a pose if detected.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Option<PoseDetection> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
 
-        let mut pose = Pose::default();
-        pose.data.copy_from_slice(landmarks.as_slice());
-        Some(pose)
+    /// Processes the input frame captured at `timestamp`, returns a pose if detected.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Option<PoseDetection>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+
+        let pose = match self.buffer.first::<Pose>(0) {
+            Some(pose) => pose.clone(),
+            None => return Ok(None),
+        };
+        let detection = self.graph.packet(1)?.and_then(|p| p.data.into_detections()).and_then(|d| d.into_iter().next());
+        let roi = self.graph.packet(2)?.and_then(|p| p.data.into_rects()).and_then(|r| r.into_iter().next());
+        let segmentation_mask = match self.mask_output {
+            Some(output) => self.graph.packet(output)?.and_then(|p| p.data.into_image()),
+            None => None,
+        };
+
+        Ok(Some(PoseDetection {
+            landmarks: pose,
+            world_landmarks: self.world_output.and_then(|output| self.buffer.first::<Pose>(output)).cloned(),
+            detection,
+            roi,
+            segmentation_mask,
+        }))
+    }
+
+    /// Processes the input frame into `buffer`, returns the pose landmarks borrowed from it if
+    /// detected.
+    pub fn try_process_into<'b>(&mut self, input: &impl AsImageView, buffer: &'b mut LandmarkBuffer) -> Result<Option<&'b Pose>, Error> {
+        let timestamp = self.running_mode.timestamp(&self.graph)?;
+        self.try_process_at_into(input, timestamp, buffer)
+    }
+
+    /// Processes the input frame captured at `timestamp` into `buffer`, returns the pose landmarks
+    /// borrowed from it if detected.
+    pub fn try_process_at_into<'b>(&mut self, input: &impl AsImageView, timestamp: Duration, buffer: &'b mut LandmarkBuffer) -> Result<Option<&'b Pose>, Error> {
+        self.graph.try_process_at_into(input, timestamp, buffer)?;
+        Ok(buffer.first(0))
     }
 }
 
@@ -79,35 +367,152 @@ impl Default for PoseDetector {
     }
 }
 
+/// Configures a [MultiPoseDetector].
+#[derive(Debug, Clone)]
+pub struct MultiPoseDetectorBuilder {
+    max_poses: usize,
+    world_landmarks: bool,
+    resources: Resources,
+}
+
+impl Default for MultiPoseDetectorBuilder {
+    fn default() -> Self {
+        Self {
+            max_poses: 2,
+            world_landmarks: false,
+            resources: Resources::default(),
+        }
+    }
+}
+
+impl MultiPoseDetectorBuilder {
+    /// Sets the maximum number of poses to detect and track, defaults to 2.
+    pub fn max_poses(mut self, max_poses: usize) -> Self {
+        self.max_poses = max_poses;
+        self
+    }
+
+    /// Also returns the landmarks in world coordinates, defaults to `false`.
+    pub fn world_landmarks(mut self, world_landmarks: bool) -> Self {
+        self.world_landmarks = world_landmarks;
+        self
+    }
+
+    /// Sets where the model files are read from, defaults to the directory set with
+    /// [set_resource_root_dir].
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Panics if the detector can not be created, see [MultiPoseDetectorBuilder::try_build].
+    pub fn build(self) -> MultiPoseDetector {
+        self.try_build().expect("Failed to create multi pose detector")
+    }
+
+    pub fn try_build(self) -> Result<MultiPoseDetector, Error> {
+        self.resources.check(&[POSE_DETECTION_MODEL, pose_landmark_model(ModelComplexity::Full)])?;
+
+        let mut config = GraphConfig::new(include_str!("graphs/multi_person_pose_tracking_cpu.pbtxt"));
+        config.set_side_packet("num_poses", SidePacket::count("max_poses", self.max_poses)?)?;
+
+        let mut outputs = vec![Output {
+            type_: FeatureType::Poses,
+            name: "multi_pose_landmarks".into(),
+        }];
+        outputs.extend(pose_outputs("pose_detections", "roi_from_landmarks"));
+        if self.world_landmarks {
+            outputs.push(Output {
+                type_: FeatureType::PosesWorld,
+                name: "multi_pose_world_landmarks".into(),
+            });
+        }
+
+        let graph = Detector::try_from_config(&config, &self.resources, outputs)?;
+
+        Ok(MultiPoseDetector {
+            graph,
+            buffer: LandmarkBuffer::new(),
+            world_landmarks: self.world_landmarks,
+        })
+    }
+}
+
 pub struct MultiPoseDetector {
     graph: Detector,
+    buffer: LandmarkBuffer,
+    world_landmarks: bool,
 }
 
 impl MultiPoseDetector {
     pub fn new() -> Self {
-        let graph = Detector::new(
-            include_str!("graphs/multi_person_pose_tracking_cpu.pbtxt"),
-            vec![Output {
-                type_: FeatureType::Poses,
-                name: "multi_pose_landmarks".into(),
-            }],
-        );
+        Self::try_new().expect("Failed to create multi pose detector")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::builder().try_build()
+    }
+
+    /// Starts configuring a multi pose detector.
+    pub fn builder() -> MultiPoseDetectorBuilder {
+        MultiPoseDetectorBuilder::default()
+    }
 
-        Self { graph }
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
     }
 
     /// Processes the input frame, returns poses if detected.
-    pub fn process(&mut self, input: &mut Mat) -> Vec<Pose> {
-        let result = self.graph.process(input);
-        let mut poses = vec![];
-
-        for pose_landmarks in result[0].iter() {
-            let mut pose = Pose::default();
-            pose.data.copy_from_slice(&pose_landmarks[..]);
-            poses.push(pose);
-        }
+    pub fn process(&mut self, input: &impl AsImageView) -> Vec<PoseDetection> {
+        self.try_process(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns poses if detected.
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Vec<PoseDetection>, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns poses if detected.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Vec<PoseDetection> {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns poses if detected.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Vec<PoseDetection>, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
 
-        poses
+        let poses = self.buffer.features::<Pose>(0).collect::<Vec<_>>();
+        let detections = per_pose(self.graph.packet(1)?.and_then(|p| p.data.into_detections()), &poses);
+        let rois = per_pose(self.graph.packet(2)?.and_then(|p| p.data.into_rects()), &poses);
+
+        let mut world_poses = if self.world_landmarks { Some(self.buffer.features::<Pose>(3)) } else { None };
+        Ok(self
+            .buffer
+            .features::<Pose>(0)
+            .zip(detections.into_iter().zip(rois))
+            .map(|(pose, (detection, roi))| PoseDetection {
+                landmarks: pose.clone(),
+                world_landmarks: world_poses.as_mut().and_then(|w| w.next()).cloned(),
+                detection,
+                roi,
+                segmentation_mask: None,
+            })
+            .collect())
+    }
+
+    /// Processes the input frame into `buffer`, returns the pose landmarks borrowed from it.
+    pub fn try_process_into<'b>(&mut self, input: &impl AsImageView, buffer: &'b mut LandmarkBuffer) -> Result<Features<'b, Pose>, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at_into(input, timestamp, buffer)
+    }
+
+    /// Processes the input frame captured at `timestamp` into `buffer`, returns the pose landmarks
+    /// borrowed from it.
+    pub fn try_process_at_into<'b>(&mut self, input: &impl AsImageView, timestamp: Duration, buffer: &'b mut LandmarkBuffer) -> Result<Features<'b, Pose>, Error> {
+        self.graph.try_process_at_into(input, timestamp, buffer)?;
+        Ok(buffer.features(0))
     }
 }
 
@@ -116,3 +521,71 @@ impl Default for MultiPoseDetector {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pose with its face around (`x`, 0.2) and its other landmarks spread down to y = 0.9.
+    fn pose_at(x: f32) -> Pose {
+        let mut pose = Pose::default();
+        for (i, l) in pose.data.iter_mut().enumerate() {
+            let face = i <= PoseLandmark::MOUTH_RIGHT as usize;
+            l.x = x + if i % 2 == 0 { 0.02 } else { -0.02 };
+            l.y = if face { 0.2 } else { 0.3 + 0.6 * (i as f32 - 11.0) / 21.0 };
+        }
+        pose
+    }
+
+    fn face_detection(x: f32) -> Detection {
+        Detection {
+            score: 0.9,
+            bbox: BoundingBox {
+                xmin: x - 0.05,
+                ymin: 0.15,
+                width: 0.1,
+                height: 0.1,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn roi(x: f32) -> Rect {
+        Rect {
+            x_center: x,
+            y_center: 0.5,
+            width: 0.3,
+            height: 0.8,
+            rotation: 0.0,
+        }
+    }
+
+    #[test]
+    fn regions_are_matched_by_position() {
+        let poses = [pose_at(0.2), pose_at(0.7)];
+        let poses = poses.iter().collect::<Vec<_>>();
+
+        // Reported in the opposite order of the poses.
+        let rois = per_pose(Some(vec![roi(0.72), roi(0.21)]), &poses);
+        assert_eq!(rois.iter().map(|r| r.map(|r| r.x_center)).collect::<Vec<_>>(), [Some(0.21), Some(0.72)]);
+
+        let detections = per_pose(Some(vec![face_detection(0.7), face_detection(0.2)]), &poses);
+        assert_eq!(detections.iter().map(|d| d.as_ref().map(|d| d.bbox.xmin + 0.05)).collect::<Vec<_>>(), [Some(0.2), Some(0.7)]);
+    }
+
+    #[test]
+    fn poses_without_a_covering_region_get_none() {
+        let poses = [pose_at(0.2), pose_at(0.7)];
+        let poses = poses.iter().collect::<Vec<_>>();
+
+        // As many detections as poses, but one of them is elsewhere in the frame.
+        let detections = per_pose(Some(vec![face_detection(0.2), face_detection(0.45)]), &poses);
+        assert!(detections[0].is_some() && detections[1].is_none());
+
+        // Two ROIs covering the same pose, the closer one wins.
+        let rois = per_pose(Some(vec![roi(0.25), roi(0.2)]), &poses[..1]);
+        assert_eq!(rois.iter().map(|r| r.map(|r| r.x_center)).collect::<Vec<_>>(), [Some(0.2)]);
+
+        assert!(per_pose::<Rect>(None, &poses).iter().all(Option::is_none));
+    }
+}
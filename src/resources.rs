@@ -0,0 +1,178 @@
+//! Locating the model files loaded by the graphs.
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    ffi::CString,
+    fmt,
+    path::{Path, PathBuf},
+    ptr,
+    sync::Mutex,
+};
+
+use super::{bindings::*, Error};
+
+/// The directory set with [set_resource_root_dir], the current directory if unset.
+static RESOURCE_ROOT_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Sets the directory the model files are read from, for detectors without their own
+/// [Resources] directory.
+///
+/// Panics if the path contains a nul byte, see [try_set_resource_root_dir].
+pub fn set_resource_root_dir(path: &Path) {
+    try_set_resource_root_dir(path).expect("Failed to set resource root dir")
+}
+
+/// Sets the directory the model files are read from, for detectors without their own
+/// [Resources] directory.
+pub fn try_set_resource_root_dir(path: &Path) -> Result<(), Error> {
+    let cstr = path_to_cstring(path)?;
+    unsafe { mediagraph_set_resource_root_dir(cstr.as_ptr()) };
+    *RESOURCE_ROOT_DIR.lock().unwrap_or_else(|e| e.into_inner()) = Some(path.to_path_buf());
+    Ok(())
+}
+
+/// Converts a path for the C++ library, which takes the raw bytes on unix.
+fn path_to_cstring(path: &Path) -> Result<CString, Error> {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_str().ok_or_else(|| Error::InvalidGraphConfig(format!("path {} is not valid unicode", path.display())))?.as_bytes().to_vec();
+
+    CString::new(bytes).map_err(|_| Error::InvalidGraphConfig(format!("path {} contains a nul byte", path.display())))
+}
+
+/// Where a detector reads its model files from.
+///
+/// Files are looked up by their path relative to the resource root, e.g.
+/// `mediapipe/modules/pose_landmark/pose_landmark_full.tflite`. Files added in memory take
+/// precedence, the others are read from the directory, which defaults to the one set with
+/// [set_resource_root_dir]. Absolute paths, e.g. of user supplied models, are read as they are.
+#[derive(Clone, Default)]
+pub struct Resources {
+    root_dir: Option<PathBuf>,
+    files: BTreeMap<String, Cow<'static, [u8]>>,
+}
+
+impl Resources {
+    /// Reads the model files from `root_dir`, which contains `mediapipe/modules`.
+    pub fn dir(root_dir: impl AsRef<Path>) -> Self {
+        Self {
+            root_dir: Some(root_dir.as_ref().to_path_buf()),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Serves the file at `path` from `data` instead of reading it from disk.
+    pub fn with_file(mut self, path: impl Into<String>, data: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.files.insert(path.into(), data.into());
+        self
+    }
+
+    /// The models of all bundled graphs, compiled into the binary from the mediapipe submodule.
+    ///
+    /// This adds tens of megabytes to the binary, most of it the heavy pose model. The blendshape
+    /// model of [FaceMeshDetectorBuilder::face_geometry](crate::face_mesh::FaceMeshDetectorBuilder::face_geometry)
+    /// is not part of the submodule, add it with [Resources::with_file].
+    #[cfg(feature = "embedded-models")]
+    pub fn embedded() -> Self {
+        macro_rules! embed {
+            ($($path:literal),* $(,)?) => {
+                Self::default()$(.with_file($path, &include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/mediapipe/", $path))[..]))*
+            };
+        }
+
+        embed!(
+            "mediapipe/modules/face_detection/face_detection_short_range.tflite",
+            "mediapipe/modules/face_detection/face_detection_full_range_sparse.tflite",
+            "mediapipe/modules/face_landmark/face_landmark.tflite",
+            "mediapipe/modules/face_landmark/face_landmark_with_attention.tflite",
+            "mediapipe/modules/face_geometry/data/geometry_pipeline_metadata_landmarks.binarypb",
+            "mediapipe/modules/palm_detection/palm_detection_lite.tflite",
+            "mediapipe/modules/palm_detection/palm_detection_full.tflite",
+            "mediapipe/modules/hand_landmark/hand_landmark_lite.tflite",
+            "mediapipe/modules/hand_landmark/hand_landmark_full.tflite",
+            "mediapipe/modules/holistic_landmark/hand_recrop.tflite",
+            "mediapipe/modules/pose_detection/pose_detection.tflite",
+            "mediapipe/modules/pose_landmark/pose_landmark_lite.tflite",
+            "mediapipe/modules/pose_landmark/pose_landmark_full.tflite",
+            "mediapipe/modules/pose_landmark/pose_landmark_heavy.tflite",
+            "mediapipe/modules/selfie_segmentation/selfie_segmentation.tflite",
+        )
+    }
+
+    /// The directory files not held in memory are read from.
+    fn root_dir(&self) -> PathBuf {
+        match &self.root_dir {
+            Some(dir) => dir.clone(),
+            None => RESOURCE_ROOT_DIR.lock().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_else(|| PathBuf::from(".")),
+        }
+    }
+
+    /// Checks that all `files` can be found, so a missing model is reported by name instead of
+    /// failing somewhere inside the graph.
+    pub(crate) fn check(&self, files: &[&str]) -> Result<(), Error> {
+        let root_dir = self.root_dir();
+        match files.iter().find(|&&f| !self.files.contains_key(f) && !root_dir.join(f).is_file()) {
+            Some(missing) => Err(Error::MissingResource(format!("{} not found in {}", missing, root_dir.display()))),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the detector uses the global resource root and no files in memory.
+    pub(crate) fn is_global(&self) -> bool {
+        self.root_dir.is_none() && self.files.is_empty()
+    }
+
+    /// Converts the locator for the C++ library. The result borrows `self`.
+    pub(crate) fn to_raw(&self) -> Result<RawResources<'_>, Error> {
+        let root_dir = self.root_dir.as_deref().map(path_to_cstring).transpose()?;
+        let paths = self.files.keys().map(|p| CString::new(p.as_str())).collect::<Result<Vec<_>, _>>().map_err(|e| Error::InvalidGraphConfig(e.to_string()))?;
+        let files = paths
+            .iter()
+            .zip(self.files.values())
+            .map(|(path, data)| mediagraph_Resource {
+                path: path.as_ptr(),
+                data: data.as_ptr(),
+                size: data.len(),
+            })
+            .collect();
+
+        Ok(RawResources {
+            root_dir,
+            _paths: paths,
+            files,
+            _data: &self.files,
+        })
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resources")
+            .field("root_dir", &self.root_dir)
+            .field("files", &self.files.iter().map(|(path, data)| (path, data.len())).collect::<BTreeMap<_, _>>())
+            .finish()
+    }
+}
+
+/// The C++ descriptor of [Resources] and the strings and buffers it points to.
+pub(crate) struct RawResources<'a> {
+    root_dir: Option<CString>,
+    _paths: Vec<CString>,
+    files: Vec<mediagraph_Resource>,
+    _data: &'a BTreeMap<String, Cow<'static, [u8]>>,
+}
+
+impl RawResources<'_> {
+    /// The descriptor, valid as long as `self`.
+    pub(crate) fn as_raw(&self) -> mediagraph_Resources {
+        mediagraph_Resources {
+            root_dir: self.root_dir.as_ref().map_or(ptr::null(), |d| d.as_ptr()),
+            files: self.files.as_ptr(),
+            num_files: self.files.len() as u32,
+        }
+    }
+}
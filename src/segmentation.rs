@@ -1,20 +1,105 @@
 //! Selfie segmentation utilities.
 use super::*;
 
+const SELFIE_SEGMENTATION_MODEL: &str = "mediapipe/modules/selfie_segmentation/selfie_segmentation.tflite";
+
 pub struct Segmentor {
-    graph: Effect,
+    graph: Detector,
+    buffer: LandmarkBuffer,
 }
 
 impl Segmentor {
     pub fn new() -> Self {
-        let graph = Effect::new(include_str!("graphs/selfie_segmentation_cpu.pbtxt"), "output_video");
+        Self::try_new().expect("Failed to create segmentor")
+    }
+
+    pub fn try_new() -> Result<Self, Error> {
+        Self::try_with_resources(Resources::default())
+    }
+
+    /// Creates a segmentor that reads its model from `resources`.
+    ///
+    /// Panics if the segmentor can not be created, see [Segmentor::try_with_resources].
+    pub fn with_resources(resources: Resources) -> Self {
+        Self::try_with_resources(resources).expect("Failed to create segmentor")
+    }
+
+    /// Creates a segmentor that reads its model from `resources`.
+    pub fn try_with_resources(resources: Resources) -> Result<Self, Error> {
+        resources.check(&[SELFIE_SEGMENTATION_MODEL])?;
+
+        let outputs = vec![
+            Output {
+                type_: FeatureType::Image,
+                name: "output_video".into(),
+            },
+            Output {
+                type_: FeatureType::Image,
+                name: "segmentation_mask".into(),
+            },
+        ];
+
+        let config = GraphConfig::new(include_str!("graphs/selfie_segmentation_cpu.pbtxt"));
+        let graph = Detector::try_from_config(&config, &resources, outputs)?;
+
+        Ok(Self { graph, buffer: LandmarkBuffer::new() })
+    }
+
+    /// Sets the pixel format of the input frames, defaults to [PixelFormat::Rgb].
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.graph.set_pixel_format(format);
+    }
+
+    /// Runs the graph on the input frame and returns the image of `output`, empty if there is none.
+    fn run(&mut self, input: &impl AsImageView, timestamp: Duration, output: usize) -> Result<Mat, Error> {
+        self.graph.try_process_at_into(input, timestamp, &mut self.buffer)?;
+        Ok(self.graph.packet(output)?.and_then(|p| p.data.into_image()).unwrap_or_default())
+    }
 
-        Self { graph }
+    /// Processes the input frame, returns the output frame.
+    pub fn process(&mut self, input: &impl AsImageView) -> Mat {
+        self.try_process(input).expect("Failed to process frame")
     }
 
     /// Processes the input frame, returns the output frame.
-    pub fn process(&mut self, input: &mut Mat) -> Mat {
-        self.graph.process(input)
+    pub fn try_process(&mut self, input: &impl AsImageView) -> Result<Mat, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_process_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the output frame.
+    pub fn process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Mat {
+        self.try_process_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the output frame.
+    pub fn try_process_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Mat, Error> {
+        self.run(input, timestamp, 0)
+    }
+
+    /// Processes the input frame, returns the segmentation mask.
+    pub fn segment(&mut self, input: &impl AsImageView) -> Mat {
+        self.try_segment(input).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame, returns the segmentation mask.
+    pub fn try_segment(&mut self, input: &impl AsImageView) -> Result<Mat, Error> {
+        let timestamp = self.graph.next_timestamp();
+        self.try_segment_at(input, timestamp)
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the segmentation mask.
+    pub fn segment_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Mat {
+        self.try_segment_at(input, timestamp).expect("Failed to process frame")
+    }
+
+    /// Processes the input frame captured at `timestamp`, returns the segmentation mask.
+    ///
+    /// The mask is a `CV_32FC1` image of the frame size, each pixel holds the confidence (0 to 1)
+    /// that it belongs to a person. It is empty if the graph did not produce a mask for this
+    /// frame.
+    pub fn try_segment_at(&mut self, input: &impl AsImageView, timestamp: Duration) -> Result<Mat, Error> {
+        self.run(input, timestamp, 1)
     }
 }
 